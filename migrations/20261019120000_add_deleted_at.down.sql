@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS modules_deleted_at_idx;
+DROP INDEX IF EXISTS projects_deleted_at_idx;
+
+ALTER TABLE modules DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE projects DROP COLUMN IF EXISTS deleted_at;
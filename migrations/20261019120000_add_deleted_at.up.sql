@@ -0,0 +1,5 @@
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS deleted_at timestamptz;
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS deleted_at timestamptz;
+
+CREATE INDEX IF NOT EXISTS projects_deleted_at_idx ON projects (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS modules_deleted_at_idx ON modules (deleted_at) WHERE deleted_at IS NOT NULL;
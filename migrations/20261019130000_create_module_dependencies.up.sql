@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS module_dependencies (
+    module_id int8 NOT NULL REFERENCES modules(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    dependency_id int8 NOT NULL REFERENCES modules(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (module_id, dependency_id),
+    CHECK (module_id <> dependency_id)
+);
+
+CREATE INDEX IF NOT EXISTS module_dependencies_dependency_id_idx ON module_dependencies (dependency_id);
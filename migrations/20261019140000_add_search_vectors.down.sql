@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS modules_search_vector_idx;
+DROP INDEX IF EXISTS projects_search_vector_idx;
+
+ALTER TABLE modules DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE projects DROP COLUMN IF EXISTS search_vector;
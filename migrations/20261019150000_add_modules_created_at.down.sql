@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS modules_project_id_updated_at_idx;
+DROP INDEX IF EXISTS projects_user_id_updated_at_idx;
+
+ALTER TABLE modules DROP COLUMN IF EXISTS created_at;
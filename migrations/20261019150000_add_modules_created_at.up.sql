@@ -0,0 +1,4 @@
+ALTER TABLE modules ADD COLUMN IF NOT EXISTS created_at timestamptz NOT NULL DEFAULT now();
+
+CREATE INDEX IF NOT EXISTS projects_user_id_updated_at_idx ON projects (user_id, updated_at, id);
+CREATE INDEX IF NOT EXISTS modules_project_id_updated_at_idx ON modules (project_id, updated_at, id);
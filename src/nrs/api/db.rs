@@ -0,0 +1,23 @@
+use std::time::Instant;
+
+use sqlx::{pool::PoolConnection, PgPool, Postgres, Transaction};
+
+/// Acquires a connection, recording the wait for the pool in `db_pool_acquire_seconds`.
+pub async fn acquire(pool: &PgPool) -> sqlx::Result<PoolConnection<Postgres>> {
+    let start = Instant::now();
+    let conn = pool.acquire().await;
+    record(start);
+    conn
+}
+
+/// Starts a transaction on a connection acquired like with [`acquire`].
+pub async fn begin(pool: &PgPool) -> sqlx::Result<Transaction<'static, Postgres>> {
+    let start = Instant::now();
+    let tx = pool.begin().await;
+    record(start);
+    tx
+}
+
+fn record(start: Instant) {
+    metrics::histogram!("db_pool_acquire_seconds").record(start.elapsed().as_secs_f64());
+}
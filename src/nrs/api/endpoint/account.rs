@@ -1,3 +1,16 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    handler::create,
+    handler::get_one,
+    handler::delete,
+    handler::update,
+    handler::login,
+    handler::change_password,
+))]
+pub(crate) struct ApiDoc;
+
 pub(crate) mod router {
     use axum::routing::{self, delete, get, post, put};
     use sqlx::{Pool, Postgres};
@@ -19,9 +32,11 @@ pub(crate) mod router {
 mod handler {
     mod request {
         use serde::Deserialize;
+        use utoipa::ToSchema;
         use validator::Validate;
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = account::Create)]
         pub struct Create {
             #[validate(length(min = 1))]
             pub login: String,
@@ -33,13 +48,15 @@ mod handler {
             pub password: String,
         }
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = account::Update)]
         pub struct Update {
             #[validate(length(min = 1))]
             pub full_name: String,
         }
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = account::ChangePassword)]
         pub struct ChangePassword {
             #[validate(length(min = 1))]
             pub old_password: String,
@@ -47,7 +64,8 @@ mod handler {
             pub new_password: String,
         }
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = account::Login)]
         pub struct Login {
             #[validate(email)]
             pub email: String,
@@ -58,13 +76,16 @@ mod handler {
 
     mod response {
         use serde::Serialize;
+        use utoipa::ToSchema;
 
-        #[derive(Serialize)]
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = account::Token)]
         pub struct Create {
             pub token: String,
         }
 
-        #[derive(Serialize)]
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = account::Account)]
         pub struct Account {
             pub login: String,
             pub full_name: String,
@@ -79,7 +100,7 @@ mod handler {
 
     use crate::{
         api::{
-            self,
+            db,
             extract::{AuthUser, ValidPayload},
             router::JwtExt,
             Error, Result,
@@ -87,6 +108,13 @@ mod handler {
         core::jwt,
     };
 
+    #[utoipa::path(
+        post,
+        path = "/",
+        tag = "account",
+        request_body = request::Create,
+        responses((status = 200, description = "Account created", body = response::Create))
+    )]
     pub async fn create(
         State(pool): State<PgPool>,
         jwt_ext: Extension<Arc<JwtExt>>,
@@ -96,6 +124,8 @@ mod handler {
             id: i64,
         }
 
+        let mut conn = db::acquire(&pool).await?;
+
         let user = sqlx::query_as!(
             User,
             "INSERT INTO users (login, full_name, email, password) values ($1, $2, $3, $4) RETURNING id",
@@ -104,46 +134,53 @@ mod handler {
             payload.email,
             payload.password
         )
-        .fetch_one(&pool)
-        .await;
-
-        match user {
-            Ok(user) => {
-                let token = jwt::create_token(user.id as i64, &payload.email, &jwt_ext.secret)
-                    .map_err(|e| {
-                        Error::InternalServerError(format!("cannot create token: {}", e))
-                    })?;
-                return Ok(Json(response::Create { token }));
-            }
-            Err(error) => match error {
-                sqlx::Error::Database(database_error) => {
-                    if database_error.is_unique_violation() {
-                        return Err(Error::Conflict);
-                    }
-                }
-                _ => return Err(api::error::Error::DatabaseError(error)),
-            },
-        }
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let token = jwt::create_token(user.id, &payload.email, &jwt_ext.secret)
+            .map_err(|e| Error::InternalServerError(format!("cannot create token: {}", e)))?;
 
-        Err(Error::InternalServerError("Unexpected error".to_string()))
+        Ok(Json(response::Create { token }))
     }
 
+    #[utoipa::path(
+        put,
+        path = "/",
+        tag = "account",
+        request_body = request::Update,
+        responses((status = 200, description = "Account updated")),
+        security(("bearer" = []))
+    )]
     pub async fn update(
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
         ValidPayload(payload): ValidPayload<request::Update>,
     ) -> Result<()> {
-        sqlx::query!(
+        let mut conn = db::acquire(&pool).await?;
+
+        let result = sqlx::query!(
             "UPDATE users SET full_name = $1, updated_at = current_timestamp WHERE id = $2",
             payload.full_name,
             user_id
         )
-        .execute(&pool)
+        .execute(&mut *conn)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound("account not found".to_string()));
+        }
+
         Ok(())
     }
 
+    #[utoipa::path(
+        put,
+        path = "/password",
+        tag = "account",
+        request_body = request::ChangePassword,
+        responses((status = 200, description = "Password changed")),
+        security(("bearer" = []))
+    )]
     pub async fn change_password(
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
@@ -153,9 +190,12 @@ mod handler {
             password: String,
         }
 
+        let mut conn = db::acquire(&pool).await?;
+
         let user = sqlx::query_as!(User, "SELECT password FROM users WHERE id = $1", user_id)
-            .fetch_one(&pool)
-            .await?;
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or_else(|| Error::NotFound("account not found".to_string()))?;
 
         if user.password != payload.old_password {
             return Err(Error::BadRequest("invalid password".to_string()));
@@ -166,74 +206,103 @@ mod handler {
             payload.new_password,
             user_id
         )
-        .execute(&pool)
+        .execute(&mut *conn)
         .await?;
 
         Ok(())
     }
 
+    #[utoipa::path(
+        post,
+        path = "/login",
+        tag = "account",
+        request_body = request::Login,
+        responses((status = 200, description = "Logged in", body = response::Create))
+    )]
     pub async fn login(
         State(pool): State<PgPool>,
         jwt_ext: Extension<Arc<JwtExt>>,
-        payload: axum::extract::Json<request::Login>,
+        ValidPayload(payload): ValidPayload<request::Login>,
     ) -> Result<Json<response::Create>> {
         struct User {
             id: i64,
             password: String,
         }
 
+        let mut conn = db::acquire(&pool).await?;
+
         let user = sqlx::query_as!(
             User,
             "SELECT id, password FROM users WHERE email = $1",
             payload.email,
         )
-        .fetch_one(&pool)
-        .await;
-
-        match user {
-            Ok(user) => {
-                if user.password != payload.password {
-                    return Err(Error::Unauthorized("wrong password".to_string()));
-                }
-
-                let token = jwt::create_token(user.id as i64, &payload.email, &jwt_ext.secret)
-                    .map_err(|e| {
-                        Error::InternalServerError(format!("cannot create token: {}", e))
-                    })?;
-
-                return Ok(Json(response::Create { token }));
-            }
-            Err(error) => match error {
-                sqlx::Error::RowNotFound => {
-                    return Err(Error::NotFound(format!("email `{}` not found", error)));
-                }
-                _ => {
-                    return Err(api::error::Error::DatabaseError(error));
-                }
-            },
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let Some(user) = user else {
+            metrics::counter!("nrs_logins_total", "result" => "failure").increment(1);
+            return Err(Error::NotFound(format!(
+                "email `{}` not found",
+                payload.email
+            )));
+        };
+
+        if user.password != payload.password {
+            metrics::counter!("nrs_logins_total", "result" => "failure").increment(1);
+            return Err(Error::Unauthorized("wrong password".to_string()));
         }
+
+        metrics::counter!("nrs_logins_total", "result" => "success").increment(1);
+
+        let token = jwt::create_token(user.id, &payload.email, &jwt_ext.secret)
+            .map_err(|e| Error::InternalServerError(format!("cannot create token: {}", e)))?;
+
+        Ok(Json(response::Create { token }))
     }
 
+    #[utoipa::path(
+        get,
+        path = "/",
+        tag = "account",
+        responses((status = 200, description = "Current account", body = response::Account)),
+        security(("bearer" = []))
+    )]
     pub async fn get_one(
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
     ) -> Result<Json<response::Account>> {
+        let mut conn = db::acquire(&pool).await?;
+
         let user = sqlx::query_as!(
             response::Account,
             "SELECT login, full_name, email FROM users WHERE id = $1",
             user_id,
         )
-        .fetch_one(&pool)
-        .await?;
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| Error::NotFound("account not found".to_string()))?;
 
         Ok(Json(user))
     }
 
+    #[utoipa::path(
+        delete,
+        path = "/",
+        tag = "account",
+        responses((status = 200, description = "Account deleted")),
+        security(("bearer" = []))
+    )]
     pub async fn delete(State(pool): State<PgPool>, AuthUser(user_id): AuthUser) -> Result<()> {
-        sqlx::query!("DELETE FROM users WHERE id = $1", user_id,)
-            .execute(&pool)
+        let mut conn = db::acquire(&pool).await?;
+
+        let result = sqlx::query!("DELETE FROM users WHERE id = $1", user_id,)
+            .execute(&mut *conn)
             .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound("account not found".to_string()));
+        }
+
         Ok(())
     }
 }
@@ -1,3 +1,4 @@
 pub mod account;
 pub mod module;
 pub mod project;
+pub mod search;
@@ -1,72 +1,284 @@
-use crate::api::Result;
-use sqlx::PgPool;
+use crate::api::{Error, Result};
+use sqlx::PgExecutor;
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    handler::get_all,
+    handler::get_one,
+    handler::create,
+    handler::update,
+    handler::delete,
+    handler::copy,
+    handler::get_trash,
+    handler::restore,
+    handler::batch,
+    handler::get_dependencies,
+    handler::set_dependencies,
+    handler::get_dependents,
+))]
+pub(crate) struct ApiDoc;
 
 pub(crate) mod router {
-    use axum::routing::{self, delete, get, post, put};
+    use axum::{
+        middleware,
+        routing::{self, delete, get, post, put},
+    };
     use sqlx::{Pool, Postgres};
 
+    use crate::api::middleware::idempotency::idempotency;
+
     use super::handler;
 
     pub fn new(pool: &Pool<Postgres>) -> routing::Router<Pool<Postgres>> {
         routing::Router::new()
             .route("/", get(handler::get_all))
             .route("/{id}", get(handler::get_one))
-            .route("/", post(handler::create))
+            .route(
+                "/",
+                post(handler::create)
+                    .route_layer(middleware::from_fn_with_state(pool.clone(), idempotency)),
+            )
             .route("/{id}", put(handler::update))
             .route("/{id}", delete(handler::delete))
+            .route("/{id}/copy", post(handler::copy))
+            .route("/trash", get(handler::get_trash))
+            .route("/{id}/restore", post(handler::restore))
+            .route("/batch", post(handler::batch))
+            .route("/{id}/dependencies", get(handler::get_dependencies))
+            .route("/{id}/dependencies", put(handler::set_dependencies))
+            .route("/{id}/dependents", get(handler::get_dependents))
             .with_state(pool.clone())
     }
 }
 
 mod handler {
-    use axum::extract::Path;
+    use std::collections::HashMap;
+
     use axum::{extract::State, Json};
-    use sqlx::PgPool;
+    use sqlx::{PgConnection, PgPool};
+    use time::OffsetDateTime;
 
-    use crate::api::endpoint::module::next_module_suffix;
-    use crate::api::extract::ValidPayload;
-    use crate::api::Result;
+    use crate::api::endpoint::module::{
+        base_name, check_dependents, check_module, delete_subtree, is_descendant,
+        next_module_suffix,
+    };
+    use crate::api::endpoint::project;
+    use crate::api::extract::{
+        list_query::ListParams, AuthUser, Cached, Conditional, ListQuery, Page, Path, Query,
+        ValidPayload,
+    };
+    use crate::api::{db, Error, Result};
+
+    const MAX_BATCH_OPERATIONS: usize = 1000;
 
     mod request {
         use serde::Deserialize;
+        use utoipa::{IntoParams, ToSchema};
         use validator::Validate;
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = module::Create)]
         pub struct Create {
             pub module_id: Option<i64>,
         }
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = module::Update)]
         pub struct Update {
             pub module_id: Option<i64>,
             #[validate(length(min = 1))]
             pub name: String,
             pub visibility: i16,
         }
+
+        #[derive(Deserialize, IntoParams)]
+        #[into_params(parameter_in = Query)]
+        pub struct Delete {
+            /// Delete even if other modules import the subtree.
+            #[serde(default)]
+            pub force: bool,
+        }
+
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = module::Dependencies)]
+        pub struct Dependencies {
+            pub dependencies: Vec<i64>,
+        }
+
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = module::Copy)]
+        pub struct Copy {
+            pub project_id: Option<i64>,
+            pub module_id: Option<i64>,
+        }
+
+        /// Either an existing module id or a `temp_id` given by an earlier `create` operation.
+        #[derive(Deserialize, ToSchema)]
+        #[serde(untagged)]
+        #[schema(as = module::ModuleRef)]
+        pub enum ModuleRef {
+            Id(i64),
+            Temp(String),
+        }
+
+        #[derive(Deserialize, ToSchema)]
+        #[serde(tag = "op", rename_all = "snake_case")]
+        #[schema(as = module::Operation)]
+        pub enum Operation {
+            Create {
+                temp_id: Option<String>,
+                module_id: Option<ModuleRef>,
+                name: Option<String>,
+                #[serde(default)]
+                visibility: i16,
+            },
+            Update {
+                id: ModuleRef,
+                name: Option<String>,
+                visibility: Option<i16>,
+            },
+            Move {
+                id: ModuleRef,
+                module_id: Option<ModuleRef>,
+            },
+            Delete {
+                id: ModuleRef,
+                #[serde(default)]
+                force: bool,
+            },
+        }
+
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = module::Batch)]
+        pub struct Batch {
+            pub operations: Vec<Operation>,
+        }
     }
 
     mod response {
         use serde::Serialize;
         use time::OffsetDateTime;
+        use utoipa::ToSchema;
+
+        use crate::api::extract::{
+            list_query::{SortField, SortValue},
+            Listed,
+        };
 
-        #[derive(Serialize)]
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = module::Created)]
         pub struct Create {
             pub id: i64,
             pub name: String,
             pub visibility: i16,
         }
 
-        #[derive(Serialize)]
+        #[derive(Serialize, ToSchema, sqlx::FromRow)]
+        #[schema(as = module::Module)]
         pub struct Module {
             pub id: i64,
             pub project_id: i64,
             pub module_id: Option<i64>,
             pub name: String,
             pub visibility: i16,
+            #[schema(value_type = Vec<i32>)]
+            pub created_at: OffsetDateTime,
+            #[schema(value_type = Vec<i32>)]
             pub updated_at: OffsetDateTime,
         }
+
+        impl Listed for Module {
+            fn id(&self) -> i64 {
+                self.id
+            }
+
+            fn sort_value(&self, field: SortField) -> SortValue {
+                match field {
+                    SortField::Name => SortValue::Text(self.name.clone()),
+                    SortField::Created => SortValue::Time(self.created_at),
+                    SortField::Updated => SortValue::Time(self.updated_at),
+                }
+            }
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = module::DeletedModule)]
+        pub struct DeletedModule {
+            pub id: i64,
+            pub module_id: Option<i64>,
+            pub name: String,
+            #[schema(value_type = Vec<i32>)]
+            pub deleted_at: OffsetDateTime,
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = module::Dependency)]
+        pub struct Dependency {
+            pub id: i64,
+            pub name: String,
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = module::CopyResult)]
+        pub struct Copy {
+            pub id: i64,
+            pub name: String,
+            pub modules: Vec<CopiedModule>,
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = module::CopiedModule)]
+        pub struct CopiedModule {
+            pub source_id: i64,
+            pub id: i64,
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[serde(tag = "op", rename_all = "snake_case")]
+        #[schema(as = module::OperationResult)]
+        pub enum OperationResult {
+            Create {
+                temp_id: Option<String>,
+                id: i64,
+                name: String,
+                visibility: i16,
+            },
+            Update {
+                id: i64,
+            },
+            Move {
+                id: i64,
+            },
+            Delete {
+                id: i64,
+                count: u64,
+            },
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = module::BatchResult)]
+        pub struct Batch {
+            pub results: Vec<OperationResult>,
+        }
     }
 
+    #[utoipa::path(
+        post,
+        path = "/",
+        tag = "modules",
+        params(
+            ("project_id" = i64, Path, description = "Project id"),
+            ("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for retries with the same key")
+        ),
+        request_body = request::Create,
+        responses(
+            (status = 200, description = "Module created", body = response::Create),
+            (status = 401, description = "Key sent without authentication"),
+            (status = 409, description = "Request with the same key in progress"),
+            (status = 422, description = "Key used with a different request")
+        )
+    )]
     pub async fn create(
         Path(project_id): Path<i64>,
         State(pool): State<PgPool>,
@@ -76,8 +288,15 @@ mod handler {
             id: i64,
         }
 
+        let mut tx = db::begin(&pool).await?;
+
+        // A trashed parent would take the module along when it is purged.
+        if let Some(module_id) = payload.module_id {
+            check_module(project_id, module_id, &mut *tx).await?;
+        }
+
         let name = "Module.".to_owned()
-            + &next_module_suffix(project_id, payload.module_id, &pool).await?;
+            + &next_module_suffix("Module", project_id, payload.module_id, &mut *tx).await?;
 
         let visibility = 0;
 
@@ -89,9 +308,11 @@ mod handler {
             name,
             visibility,
         )
-        .fetch_one(&pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        tx.commit().await?;
+
         Ok(Json(response::Create {
             id: module.id,
             name,
@@ -99,73 +320,872 @@ mod handler {
         }))
     }
 
+    #[utoipa::path(
+        put,
+        path = "/{id}",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        request_body = request::Update,
+        responses((status = 200, description = "Module updated"))
+    )]
     pub async fn update(
-        Path(id): Path<i64>,
+        Path((project_id, id)): Path<(i64, i64)>,
         State(pool): State<PgPool>,
         ValidPayload(payload): ValidPayload<request::Update>,
     ) -> Result<()> {
-        sqlx::query!(
-            "UPDATE modules SET module_id = $1, name = $2, visibility = $3, updated_at = current_timestamp WHERE id = $4",
+        let mut tx = db::begin(&pool).await?;
+
+        if let Some(module_id) = payload.module_id {
+            check_module(project_id, module_id, &mut *tx).await?;
+
+            if is_descendant(id, module_id, &mut *tx).await? {
+                return Err(Error::BadRequest(format!(
+                    "cannot move module `{id}` into its own subtree"
+                )));
+            }
+        }
+
+        let result = sqlx::query!(
+            "UPDATE modules SET module_id = $1, name = $2, visibility = $3, updated_at = current_timestamp WHERE id = $4 AND project_id = $5 AND deleted_at IS NULL",
             payload.module_id,
             payload.name,
             payload.visibility,
             id,
+            project_id,
         )
-        .execute(&pool)
+        .execute(&mut *tx)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!("module `{id}` not found")));
+        }
+
+        tx.commit().await?;
+
         Ok(())
     }
 
+    #[utoipa::path(
+        get,
+        path = "/",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ListParams),
+        responses(
+            (status = 200, description = "Page of modules", body = Vec<response::Module>),
+            (status = 304, description = "Not modified")
+        )
+    )]
     pub async fn get_all(
         Path(project_id): Path<i64>,
         State(pool): State<PgPool>,
-    ) -> Result<Json<Vec<response::Module>>> {
-        let projects = sqlx::query_as!(
+        list: ListQuery,
+        conditional: Conditional,
+    ) -> Result<Cached<Page<response::Module>>> {
+        if list.target.is_some() {
+            return Err(Error::BadRequest(
+                "`target` filter is not supported for modules".to_string(),
+            ));
+        }
+
+        let mut query = sqlx::query_builder::QueryBuilder::new(
+            "SELECT id, project_id, module_id, name, visibility, created_at, updated_at
+            FROM modules
+            WHERE deleted_at IS NULL
+                AND project_id IN (SELECT id FROM projects WHERE deleted_at IS NULL)
+                AND project_id = ",
+        );
+
+        query.push_bind(project_id);
+
+        list.push_conditions(&mut query);
+        list.push_order(&mut query);
+
+        let mut conn = db::acquire(&pool).await?;
+
+        let modules = query
+            .build_query_as::<response::Module>()
+            .fetch_all(&mut *conn)
+            .await?;
+
+        Ok(conditional.page(list.page(modules)))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/{id}",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        responses(
+            (status = 200, description = "Module", body = response::Module),
+            (status = 304, description = "Not modified")
+        )
+    )]
+    pub async fn get_one(
+        Path((project_id, id)): Path<(i64, i64)>,
+        State(pool): State<PgPool>,
+        conditional: Conditional,
+    ) -> Result<Cached<Json<response::Module>>> {
+        let mut conn = db::acquire(&pool).await?;
+
+        let module = sqlx::query_as!(
             response::Module,
-            "SELECT id, project_id, module_id, name, visibility, updated_at
+            "SELECT id, project_id, module_id, name, visibility, created_at, updated_at
             FROM modules
-            WHERE project_id = $1
-            ORDER BY updated_at DESC",
+            WHERE id = $1 AND project_id = $2 AND deleted_at IS NULL
+                AND project_id IN (SELECT id FROM projects WHERE deleted_at IS NULL)",
+            id,
             project_id,
         )
-        .fetch_all(&pool)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("module `{id}` not found")))?;
+
+        Ok(conditional.row(module.id, module.updated_at, Json(module)))
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/{id}",
+        tag = "modules",
+        params(
+            ("project_id" = i64, Path, description = "Project id"),
+            ("id" = i64, Path, description = "Module id"),
+            request::Delete,
+        ),
+        responses(
+            (status = 200, description = "Module and its descendants moved to trash"),
+            (status = 409, description = "Module is imported by other modules")
+        ),
+        security(("bearer" = []))
+    )]
+    pub async fn delete(
+        Path((project_id, id)): Path<(i64, i64)>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+        Query(params): Query<request::Delete>,
+    ) -> Result<()> {
+        let mut tx = db::begin(&pool).await?;
+
+        project::check_owner(project_id, user_id, &mut *tx).await?;
+        check_module(project_id, id, &mut *tx).await?;
+
+        if !params.force {
+            check_dependents(id, &mut *tx).await?;
+        }
+
+        if delete_subtree(project_id, id, &mut *tx).await? == 0 {
+            return Err(Error::NotFound(format!("module `{id}` not found")));
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/{id}/dependencies",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        responses((status = 200, description = "Modules imported by the module", body = Vec<response::Dependency>))
+    )]
+    pub async fn get_dependencies(
+        Path((project_id, id)): Path<(i64, i64)>,
+        State(pool): State<PgPool>,
+    ) -> Result<Json<Vec<response::Dependency>>> {
+        let mut conn = db::acquire(&pool).await?;
+
+        check_module(project_id, id, &mut *conn).await?;
+
+        let dependencies = sqlx::query_as!(
+            response::Dependency,
+            "SELECT m.id, m.name
+            FROM module_dependencies md
+            JOIN modules m ON m.id = md.dependency_id
+            WHERE md.module_id = $1 AND m.deleted_at IS NULL
+            ORDER BY m.name",
+            id,
+        )
+        .fetch_all(&mut *conn)
         .await?;
 
-        Ok(Json(projects))
+        Ok(Json(dependencies))
     }
 
-    pub async fn get_one(
-        Path(id): Path<i64>,
+    #[utoipa::path(
+        get,
+        path = "/{id}/dependents",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        responses((status = 200, description = "Modules importing the module", body = Vec<response::Dependency>))
+    )]
+    pub async fn get_dependents(
+        Path((project_id, id)): Path<(i64, i64)>,
         State(pool): State<PgPool>,
-    ) -> Result<Json<response::Module>> {
-        let project = sqlx::query_as!(
-            response::Module,
-            "SELECT id, project_id, module_id, name, visibility, updated_at
-            FROM modules
-            WHERE id = $1",
+    ) -> Result<Json<Vec<response::Dependency>>> {
+        let mut conn = db::acquire(&pool).await?;
+
+        check_module(project_id, id, &mut *conn).await?;
+
+        let dependents = sqlx::query_as!(
+            response::Dependency,
+            "SELECT m.id, m.name
+            FROM module_dependencies md
+            JOIN modules m ON m.id = md.module_id
+            WHERE md.dependency_id = $1 AND m.deleted_at IS NULL
+            ORDER BY m.name",
             id,
         )
-        .fetch_one(&pool)
+        .fetch_all(&mut *conn)
         .await?;
 
-        Ok(Json(project))
+        Ok(Json(dependents))
     }
 
-    pub async fn delete(Path(id): Path<i64>, State(pool): State<PgPool>) -> Result<()> {
-        sqlx::query!("DELETE FROM modules WHERE id = $1", id)
-            .execute(&pool)
+    #[utoipa::path(
+        put,
+        path = "/{id}/dependencies",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        request_body = request::Dependencies,
+        responses((status = 200, description = "Dependencies replaced")),
+        security(("bearer" = []))
+    )]
+    pub async fn set_dependencies(
+        Path((project_id, id)): Path<(i64, i64)>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+        ValidPayload(payload): ValidPayload<request::Dependencies>,
+    ) -> Result<()> {
+        let mut tx = db::begin(&pool).await?;
+
+        project::check_owner(project_id, user_id, &mut *tx).await?;
+
+        // Dependencies stay within a project, so serializing its updates keeps two
+        // concurrent ones from each passing the cycle check and closing a cycle together.
+        sqlx::query!(
+            "SELECT id FROM projects WHERE id = $1 FOR NO KEY UPDATE",
+            project_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        check_module(project_id, id, &mut *tx).await?;
+
+        for dependency_id in payload.dependencies.iter() {
+            check_module(project_id, *dependency_id, &mut *tx).await?;
+        }
+
+        // Looks for a path from one of the new dependencies back to the module itself.
+        let cycle = sqlx::query_scalar!(
+            r#"WITH RECURSIVE reachable (id, path) AS (
+                SELECT dependency_id, ARRAY[$1, dependency_id]
+                FROM unnest($2::int8[]) AS dependency_id
+                UNION ALL
+                SELECT md.dependency_id, r.path || md.dependency_id
+                FROM module_dependencies md
+                JOIN reachable r ON md.module_id = r.id
+                WHERE r.id <> $1 AND NOT md.dependency_id = ANY(r.path[2:])
+            )
+            SELECT path AS "path!" FROM reachable WHERE id = $1 LIMIT 1"#,
+            id,
+            &payload.dependencies,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(cycle) = cycle {
+            let cycle = cycle
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(Error::BadRequest(format!("import cycle: {cycle}")));
+        }
+
+        sqlx::query!("DELETE FROM module_dependencies WHERE module_id = $1", id)
+            .execute(&mut *tx)
             .await?;
 
+        sqlx::query!(
+            "INSERT INTO module_dependencies (module_id, dependency_id)
+            SELECT $1, dependency_id FROM unnest($2::int8[]) AS dependency_id
+            ON CONFLICT DO NOTHING",
+            id,
+            &payload.dependencies,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
+
+    #[utoipa::path(
+        get,
+        path = "/trash",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id")),
+        responses((status = 200, description = "Modules in trash", body = Vec<response::DeletedModule>)),
+        security(("bearer" = []))
+    )]
+    pub async fn get_trash(
+        Path(project_id): Path<i64>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+    ) -> Result<Json<Vec<response::DeletedModule>>> {
+        let mut conn = db::acquire(&pool).await?;
+
+        project::check_owner(project_id, user_id, &mut *conn).await?;
+
+        // Only subtree roots are listed, their descendants are restored with them.
+        let modules = sqlx::query_as!(
+            response::DeletedModule,
+            r#"SELECT m.id, m.module_id, m.name, m.deleted_at AS "deleted_at!"
+            FROM modules m
+            LEFT JOIN modules p ON p.id = m.module_id
+            WHERE m.project_id = $1
+                AND m.deleted_at IS NOT NULL
+                AND p.deleted_at IS DISTINCT FROM m.deleted_at
+            ORDER BY m.deleted_at DESC"#,
+            project_id,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(Json(modules))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/{id}/restore",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        responses((status = 200, description = "Module restored from trash")),
+        security(("bearer" = []))
+    )]
+    pub async fn restore(
+        Path((project_id, id)): Path<(i64, i64)>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+    ) -> Result<()> {
+        struct Module {
+            deleted_at: Option<OffsetDateTime>,
+            parent_deleted_at: Option<OffsetDateTime>,
+        }
+
+        let mut tx = db::begin(&pool).await?;
+
+        project::check_owner(project_id, user_id, &mut *tx).await?;
+
+        let module = sqlx::query_as!(
+            Module,
+            "SELECT m.deleted_at, p.deleted_at AS parent_deleted_at
+            FROM modules m
+            LEFT JOIN modules p ON p.id = m.module_id
+            WHERE m.id = $1 AND m.project_id = $2",
+            id,
+            project_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(deleted_at) = module.as_ref().and_then(|module| module.deleted_at) else {
+            return Err(Error::NotFound(format!("module `{id}` not found in trash")));
+        };
+
+        if module.and_then(|module| module.parent_deleted_at).is_some() {
+            return Err(Error::BadRequest(
+                "parent module must be restored first".to_string(),
+            ));
+        }
+
+        sqlx::query!(
+            "WITH RECURSIVE subtree AS (
+                SELECT id FROM modules WHERE id = $1
+                UNION ALL
+                SELECT m.id FROM modules m
+                JOIN subtree s ON m.module_id = s.id
+                WHERE m.deleted_at = $2
+            )
+            UPDATE modules SET deleted_at = NULL
+            WHERE id IN (SELECT id FROM subtree)",
+            id,
+            deleted_at,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/{id}/copy",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id"), ("id" = i64, Path, description = "Module id")),
+        request_body = request::Copy,
+        responses((status = 200, description = "Copied subtree", body = response::Copy)),
+        security(("bearer" = []))
+    )]
+    pub async fn copy(
+        Path((project_id, id)): Path<(i64, i64)>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+        ValidPayload(payload): ValidPayload<request::Copy>,
+    ) -> Result<Json<response::Copy>> {
+        struct Module {
+            id: i64,
+            module_id: Option<i64>,
+            name: String,
+            visibility: i16,
+        }
+
+        struct Inserted {
+            id: i64,
+        }
+
+        let target_project_id = payload.project_id.unwrap_or(project_id);
+
+        let mut tx = db::begin(&pool).await?;
+
+        project::check_owner(project_id, user_id, &mut *tx).await?;
+
+        if target_project_id != project_id {
+            project::check_owner(target_project_id, user_id, &mut *tx).await?;
+        }
+
+        if let Some(module_id) = payload.module_id {
+            check_module(target_project_id, module_id, &mut *tx).await?;
+        }
+
+        let modules = sqlx::query_as!(
+            Module,
+            r#"WITH RECURSIVE subtree AS (
+                SELECT id, module_id, name, visibility, 0 AS depth
+                FROM modules
+                WHERE id = $1 AND project_id = $2 AND deleted_at IS NULL
+                UNION ALL
+                SELECT m.id, m.module_id, m.name, m.visibility, s.depth + 1
+                FROM modules m
+                JOIN subtree s ON m.module_id = s.id
+                WHERE m.deleted_at IS NULL
+            )
+            SELECT id AS "id!", module_id, name AS "name!", visibility AS "visibility!"
+            FROM subtree
+            ORDER BY depth, id"#,
+            id,
+            project_id,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let Some(root) = modules.first() else {
+            return Err(Error::NotFound(format!("module `{id}` not found")));
+        };
+
+        let name_taken = sqlx::query_scalar!(
+            r#"SELECT EXISTS(
+                SELECT 1 FROM modules
+                WHERE project_id = $1
+                    AND module_id IS NOT DISTINCT FROM $2
+                    AND name = $3
+                    AND deleted_at IS NULL
+            ) AS "exists!""#,
+            target_project_id,
+            payload.module_id,
+            root.name,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let root_name = if name_taken {
+            let base = base_name(&root.name);
+            format!(
+                "{base}.{}",
+                next_module_suffix(base, target_project_id, payload.module_id, &mut *tx).await?
+            )
+        } else {
+            root.name.clone()
+        };
+
+        let mut ids = HashMap::with_capacity(modules.len());
+        let mut copied = Vec::with_capacity(modules.len());
+
+        for module in modules.iter() {
+            let (parent_id, name) = if module.id == root.id {
+                (payload.module_id, &root_name)
+            } else {
+                (
+                    module.module_id.and_then(|id| ids.get(&id).copied()),
+                    &module.name,
+                )
+            };
+
+            let inserted = sqlx::query_as!(
+                Inserted,
+                "INSERT INTO modules (project_id, module_id, name, visibility) values ($1, $2, $3, $4) RETURNING id",
+                target_project_id,
+                parent_id,
+                name,
+                module.visibility,
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+            ids.insert(module.id, inserted.id);
+
+            copied.push(response::CopiedModule {
+                source_id: module.id,
+                id: inserted.id,
+            });
+        }
+
+        // Dependencies inside the subtree follow the copies, outer ones are kept within a project.
+        let dependencies = sqlx::query!(
+            "SELECT module_id, dependency_id FROM module_dependencies WHERE module_id = ANY($1)",
+            &ids.keys().copied().collect::<Vec<_>>(),
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for dependency in dependencies {
+            let dependency_id = match ids.get(&dependency.dependency_id) {
+                Some(id) => *id,
+                None if target_project_id == project_id => dependency.dependency_id,
+                None => continue,
+            };
+
+            sqlx::query!(
+                "INSERT INTO module_dependencies (module_id, dependency_id) VALUES ($1, $2)",
+                ids[&dependency.module_id],
+                dependency_id,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(Json(response::Copy {
+            id: copied[0].id,
+            name: root_name,
+            modules: copied,
+        }))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/batch",
+        tag = "modules",
+        params(("project_id" = i64, Path, description = "Project id")),
+        request_body = request::Batch,
+        responses((status = 200, description = "Results of operations", body = response::Batch)),
+        security(("bearer" = []))
+    )]
+    pub async fn batch(
+        Path(project_id): Path<i64>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+        ValidPayload(payload): ValidPayload<request::Batch>,
+    ) -> Result<Json<response::Batch>> {
+        if payload.operations.len() > MAX_BATCH_OPERATIONS {
+            return Err(Error::BadRequest(format!(
+                "batch is limited to {MAX_BATCH_OPERATIONS} operations"
+            )));
+        }
+
+        let mut tx = db::begin(&pool).await?;
+
+        project::check_owner(project_id, user_id, &mut *tx).await?;
+
+        let mut temp_ids = HashMap::new();
+        let mut results = Vec::with_capacity(payload.operations.len());
+
+        for (index, operation) in payload.operations.into_iter().enumerate() {
+            let result = apply_operation(project_id, operation, &mut temp_ids, &mut tx)
+                .await
+                .map_err(|error| match error {
+                    Error::NotFound(message) => {
+                        Error::NotFound(format!("operation {index}: {message}"))
+                    }
+                    Error::BadRequest(message) => {
+                        Error::BadRequest(format!("operation {index}: {message}"))
+                    }
+                    error => error,
+                })?;
+
+            results.push(result);
+        }
+
+        tx.commit().await?;
+
+        Ok(Json(response::Batch { results }))
+    }
+
+    async fn apply_operation(
+        project_id: i64,
+        operation: request::Operation,
+        temp_ids: &mut HashMap<String, i64>,
+        conn: &mut PgConnection,
+    ) -> Result<response::OperationResult> {
+        struct Module {
+            id: i64,
+        }
+
+        let resolve =
+            async |module_ref: request::ModuleRef, conn: &mut PgConnection| -> Result<i64> {
+                let id = match module_ref {
+                    request::ModuleRef::Id(id) => id,
+                    request::ModuleRef::Temp(temp_id) => *temp_ids
+                        .get(&temp_id)
+                        .ok_or_else(|| Error::BadRequest(format!("unknown temp id `{temp_id}`")))?,
+                };
+
+                check_module(project_id, id, conn).await?;
+
+                Ok(id)
+            };
+
+        match operation {
+            request::Operation::Create {
+                temp_id,
+                module_id,
+                name,
+                visibility,
+            } => {
+                if let Some(temp_id) = &temp_id
+                    && temp_ids.contains_key(temp_id)
+                {
+                    return Err(Error::BadRequest(format!("duplicate temp id `{temp_id}`")));
+                }
+
+                let module_id = match module_id {
+                    Some(module_ref) => Some(resolve(module_ref, &mut *conn).await?),
+                    None => None,
+                };
+
+                let name = match name {
+                    Some(name) if name.is_empty() => {
+                        return Err(Error::BadRequest("name must not be empty".to_string()));
+                    }
+                    Some(name) => name,
+                    None => {
+                        "Module.".to_owned()
+                            + &next_module_suffix("Module", project_id, module_id, &mut *conn)
+                                .await?
+                    }
+                };
+
+                let module = sqlx::query_as!(
+                    Module,
+                    "INSERT INTO modules (project_id, module_id, name, visibility) values ($1, $2, $3, $4) RETURNING id",
+                    project_id,
+                    module_id,
+                    name,
+                    visibility,
+                )
+                .fetch_one(&mut *conn)
+                .await?;
+
+                if let Some(temp_id) = &temp_id {
+                    temp_ids.insert(temp_id.clone(), module.id);
+                }
+
+                Ok(response::OperationResult::Create {
+                    temp_id,
+                    id: module.id,
+                    name,
+                    visibility,
+                })
+            }
+            request::Operation::Update {
+                id,
+                name,
+                visibility,
+            } => {
+                let id = resolve(id, &mut *conn).await?;
+
+                if name.as_ref().is_some_and(|name| name.is_empty()) {
+                    return Err(Error::BadRequest("name must not be empty".to_string()));
+                }
+
+                sqlx::query!(
+                    "UPDATE modules SET name = COALESCE($1, name), visibility = COALESCE($2, visibility), updated_at = current_timestamp WHERE id = $3",
+                    name,
+                    visibility,
+                    id,
+                )
+                .execute(&mut *conn)
+                .await?;
+
+                Ok(response::OperationResult::Update { id })
+            }
+            request::Operation::Move { id, module_id } => {
+                let id = resolve(id, &mut *conn).await?;
+
+                let module_id = match module_id {
+                    Some(module_ref) => Some(resolve(module_ref, &mut *conn).await?),
+                    None => None,
+                };
+
+                if let Some(module_id) = module_id
+                    && is_descendant(id, module_id, &mut *conn).await?
+                {
+                    return Err(Error::BadRequest(format!(
+                        "cannot move module `{id}` into its own subtree"
+                    )));
+                }
+
+                sqlx::query!(
+                    "UPDATE modules SET module_id = $1, updated_at = current_timestamp WHERE id = $2",
+                    module_id,
+                    id,
+                )
+                .execute(&mut *conn)
+                .await?;
+
+                Ok(response::OperationResult::Move { id })
+            }
+            request::Operation::Delete { id, force } => {
+                let id = resolve(id, &mut *conn).await?;
+
+                if !force {
+                    check_dependents(id, &mut *conn).await?;
+                }
+
+                let count = delete_subtree(project_id, id, &mut *conn).await?;
+
+                Ok(response::OperationResult::Delete { id, count })
+            }
+        }
+    }
+}
+
+async fn check_module<'e, E>(project_id: i64, id: i64, executor: E) -> Result<()>
+where
+    E: PgExecutor<'e>,
+{
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM modules WHERE id = $1 AND project_id = $2 AND deleted_at IS NULL
+        ) AS "exists!""#,
+        id,
+        project_id,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    if !exists {
+        return Err(Error::NotFound(format!("module `{id}` not found")));
+    }
+
+    Ok(())
 }
 
-async fn next_module_suffix(
+/// Fails if modules outside the subtree of `id` still depend on it or its descendants.
+async fn check_dependents<'e, E>(id: i64, executor: E) -> Result<()>
+where
+    E: PgExecutor<'e>,
+{
+    let dependents = sqlx::query_scalar!(
+        r#"WITH RECURSIVE subtree AS (
+            SELECT id FROM modules WHERE id = $1
+            UNION ALL
+            SELECT m.id FROM modules m
+            JOIN subtree s ON m.module_id = s.id
+            WHERE m.deleted_at IS NULL
+        )
+        SELECT DISTINCT md.module_id AS "module_id!"
+        FROM module_dependencies md
+        JOIN modules m ON m.id = md.module_id
+        WHERE md.dependency_id IN (SELECT id FROM subtree)
+            AND md.module_id NOT IN (SELECT id FROM subtree)
+            AND m.deleted_at IS NULL
+        ORDER BY 1"#,
+        id,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    if !dependents.is_empty() {
+        let dependents = dependents
+            .iter()
+            .map(|id| format!("`{id}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        return Err(Error::InUse(format!(
+            "module `{id}` is imported by modules {dependents}, use `force` to delete it anyway"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `id` is `root` itself or one of its descendants.
+async fn is_descendant<'e, E>(root: i64, id: i64, executor: E) -> Result<bool>
+where
+    E: PgExecutor<'e>,
+{
+    let found = sqlx::query_scalar!(
+        r#"WITH RECURSIVE subtree AS (
+            SELECT id FROM modules WHERE id = $1
+            UNION ALL
+            SELECT m.id FROM modules m
+            JOIN subtree s ON m.module_id = s.id
+        )
+        SELECT EXISTS(SELECT 1 FROM subtree WHERE id = $2) AS "exists!""#,
+        root,
+        id,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(found)
+}
+
+/// Moves a module with its descendants to the trash and returns the number of affected modules.
+async fn delete_subtree<'e, E>(project_id: i64, id: i64, executor: E) -> Result<u64>
+where
+    E: PgExecutor<'e>,
+{
+    // The whole subtree shares one `deleted_at` so it can be restored together. The clock
+    // time is taken once per call, unlike the transaction time, so subtrees deleted in one
+    // batch stay apart in the trash.
+    let result = sqlx::query!(
+        "WITH RECURSIVE subtree AS (
+            SELECT id FROM modules
+            WHERE id = $1 AND project_id = $2 AND deleted_at IS NULL
+            UNION ALL
+            SELECT m.id FROM modules m
+            JOIN subtree s ON m.module_id = s.id
+            WHERE m.deleted_at IS NULL
+        )
+        UPDATE modules SET deleted_at = (SELECT clock_timestamp())
+        WHERE id IN (SELECT id FROM subtree)",
+        id,
+        project_id,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+async fn next_module_suffix<'e, E>(
+    base: &str,
     project_id: i64,
     module_id: Option<i64>,
-    pool: &PgPool,
-) -> Result<String> {
+    executor: E,
+) -> Result<String>
+where
+    E: PgExecutor<'e>,
+{
     #[derive(Debug, sqlx::FromRow)]
     struct Module {
         name: String,
@@ -183,28 +1203,30 @@ async fn next_module_suffix(
         query.push(" AND module_id IS NULL");
     };
 
+    query.push(" AND deleted_at IS NULL");
+
+    query.push(" AND starts_with(name, ");
+    query.push_bind(format!("{base}."));
+    query.push(")");
+
     query.push(" ORDER BY name ASC");
 
-    let modules = query.build_query_as::<Module>().fetch_all(pool).await?;
+    let modules = query.build_query_as::<Module>().fetch_all(executor).await?;
 
     let mut prev_num = 0;
 
     for module in modules.iter() {
-        let mut name = module.name.chars();
-        let dot = name.nth(module.name.len() - 4);
+        let suffix = module
+            .name
+            .strip_prefix(base)
+            .and_then(|name| name.strip_prefix('.'));
 
-        if let Some(dot) = dot {
-            if dot != '.' {
-                continue;
-            };
-        } else {
+        let Some(suffix) = suffix.filter(|suffix| suffix.len() == 3) else {
             continue;
-        }
-
-        let num = name.as_str().parse::<u32>();
+        };
 
-        if let Ok(num) = num {
-            if num - prev_num > 1 {
+        if let Ok(num) = suffix.parse::<u32>() {
+            if num > prev_num + 1 {
                 break;
             }
 
@@ -214,3 +1236,13 @@ async fn next_module_suffix(
 
     Ok(format!("{:0>3}", prev_num + 1))
 }
+
+/// Strips a numeric `.NNN` suffix added by [`next_module_suffix`].
+fn base_name(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((base, suffix)) if suffix.len() == 3 && suffix.bytes().all(|b| b.is_ascii_digit()) => {
+            base
+        }
+        _ => name,
+    }
+}
@@ -1,8 +1,28 @@
+use sqlx::PgExecutor;
+use utoipa::OpenApi;
+
+use crate::api::{Error, Result};
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    handler::get_all,
+    handler::get_one,
+    handler::create,
+    handler::update,
+    handler::delete,
+    handler::get_trash,
+    handler::restore,
+))]
+pub(crate) struct ApiDoc;
+
 pub(crate) mod router {
-    use axum::routing::{self, delete, get, post, put};
+    use axum::{
+        middleware,
+        routing::{self, delete, get, post, put},
+    };
     use sqlx::{Pool, Postgres};
 
-    use crate::api::endpoint;
+    use crate::api::{endpoint, middleware::idempotency::idempotency};
 
     use super::handler;
 
@@ -10,9 +30,15 @@ pub(crate) mod router {
         routing::Router::new()
             .route("/", get(handler::get_all))
             .route("/{id}", get(handler::get_one))
-            .route("/", post(handler::create))
+            .route(
+                "/",
+                post(handler::create)
+                    .route_layer(middleware::from_fn_with_state(pool.clone(), idempotency)),
+            )
             .route("/{id}", put(handler::update))
             .route("/{id}", delete(handler::delete))
+            .route("/trash", get(handler::get_trash))
+            .route("/{id}/restore", post(handler::restore))
             .nest(
                 "/{project_id}/modules",
                 endpoint::module::router::new(&pool),
@@ -22,18 +48,21 @@ pub(crate) mod router {
 }
 
 mod handler {
-    use axum::extract::Path;
     use axum::{extract::State, Json};
     use sqlx::PgPool;
 
-    use crate::api::extract::{AuthUser, ValidPayload};
-    use crate::api::Result;
+    use crate::api::extract::{
+        list_query::ListParams, AuthUser, Cached, Conditional, ListQuery, Page, Path, ValidPayload,
+    };
+    use crate::api::{db, Error, Result};
 
     mod request {
         use serde::Deserialize;
+        use utoipa::ToSchema;
         use validator::Validate;
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = project::Create)]
         pub struct Create {
             #[validate(length(min = 1))]
             pub name: String,
@@ -41,7 +70,8 @@ mod handler {
             pub description: String,
         }
 
-        #[derive(Deserialize, Validate)]
+        #[derive(Deserialize, Validate, ToSchema)]
+        #[schema(as = project::Update)]
         pub struct Update {
             #[validate(length(min = 1))]
             pub name: String,
@@ -52,23 +82,71 @@ mod handler {
     mod response {
         use serde::Serialize;
         use time::OffsetDateTime;
+        use utoipa::ToSchema;
+
+        use crate::api::extract::{
+            list_query::{SortField, SortValue},
+            Listed,
+        };
 
-        #[derive(Serialize)]
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = project::Created)]
         pub struct Create {
             pub id: i64,
         }
 
-        #[derive(Serialize)]
+        #[derive(Serialize, ToSchema, sqlx::FromRow)]
+        #[schema(as = project::Project)]
         pub struct Project {
             pub id: i64,
             pub name: String,
             pub target: i16,
             pub description: String,
+            #[schema(value_type = Vec<i32>)]
             pub created_at: OffsetDateTime,
+            #[schema(value_type = Vec<i32>)]
             pub updated_at: OffsetDateTime,
         }
+
+        impl Listed for Project {
+            fn id(&self) -> i64 {
+                self.id
+            }
+
+            fn sort_value(&self, field: SortField) -> SortValue {
+                match field {
+                    SortField::Name => SortValue::Text(self.name.clone()),
+                    SortField::Created => SortValue::Time(self.created_at),
+                    SortField::Updated => SortValue::Time(self.updated_at),
+                }
+            }
+        }
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = project::DeletedProject)]
+        pub struct DeletedProject {
+            pub id: i64,
+            pub name: String,
+            pub target: i16,
+            pub description: String,
+            #[schema(value_type = Vec<i32>)]
+            pub deleted_at: OffsetDateTime,
+        }
     }
 
+    #[utoipa::path(
+        post,
+        path = "/",
+        tag = "projects",
+        params(("Idempotency-Key" = Option<String>, Header, description = "Replays the first response for retries with the same key")),
+        request_body = request::Create,
+        responses(
+            (status = 200, description = "Project created", body = response::Create),
+            (status = 409, description = "Request with the same key in progress"),
+            (status = 422, description = "Key used with a different request")
+        ),
+        security(("bearer" = []))
+    )]
     pub async fn create(
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
@@ -78,6 +156,8 @@ mod handler {
             id: i64,
         }
 
+        let mut conn = db::acquire(&pool).await?;
+
         let project = sqlx::query_as!(
             Project,
             "INSERT INTO projects (user_id, name, target, description) values ($1, $2, $3, $4) RETURNING id",
@@ -86,81 +166,230 @@ mod handler {
             payload.target,
             payload.description,
         )
-        .fetch_one(&pool)
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(Json(response::Create { id: project.id }))
     }
 
+    #[utoipa::path(
+        put,
+        path = "/{id}",
+        tag = "projects",
+        params(("id" = i64, Path, description = "Project id")),
+        request_body = request::Update,
+        responses((status = 200, description = "Project updated")),
+        security(("bearer" = []))
+    )]
     pub async fn update(
         Path(id): Path<i64>,
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
         ValidPayload(payload): ValidPayload<request::Update>,
     ) -> Result<()> {
-        sqlx::query!(
-            "UPDATE projects SET name = $1, description = $2, updated_at = current_timestamp WHERE id = $3 AND user_id = $4",
+        let mut conn = db::acquire(&pool).await?;
+
+        let result = sqlx::query!(
+            "UPDATE projects SET name = $1, description = $2, updated_at = current_timestamp WHERE id = $3 AND user_id = $4 AND deleted_at IS NULL",
             payload.name,
             payload.description,
             id,
             user_id
         )
-        .execute(&pool)
+        .execute(&mut *conn)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!("project `{id}` not found")));
+        }
+
         Ok(())
     }
 
+    #[utoipa::path(
+        get,
+        path = "/",
+        tag = "projects",
+        params(ListParams),
+        responses(
+            (status = 200, description = "Page of projects", body = Vec<response::Project>),
+            (status = 304, description = "Not modified")
+        ),
+        security(("bearer" = []))
+    )]
     pub async fn get_all(
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
-    ) -> Result<Json<Vec<response::Project>>> {
-        let projects = sqlx::query_as!(
-            response::Project,
+        list: ListQuery,
+        conditional: Conditional,
+    ) -> Result<Cached<Page<response::Project>>> {
+        let mut query = sqlx::query_builder::QueryBuilder::new(
             "SELECT id, name, target, description, created_at, updated_at
             FROM projects
-            WHERE user_id = $1
-            ORDER BY updated_at DESC",
-            user_id,
-        )
-        .fetch_all(&pool)
-        .await?;
+            WHERE deleted_at IS NULL AND user_id = ",
+        );
 
-        Ok(Json(projects))
+        query.push_bind(user_id);
+
+        if let Some(target) = list.target {
+            query.push(" AND target = ");
+            query.push_bind(target);
+        }
+
+        list.push_conditions(&mut query);
+        list.push_order(&mut query);
+
+        let mut conn = db::acquire(&pool).await?;
+
+        let projects = query
+            .build_query_as::<response::Project>()
+            .fetch_all(&mut *conn)
+            .await?;
+
+        Ok(conditional.page(list.page(projects)))
     }
 
+    #[utoipa::path(
+        get,
+        path = "/{id}",
+        tag = "projects",
+        params(("id" = i64, Path, description = "Project id")),
+        responses(
+            (status = 200, description = "Project", body = response::Project),
+            (status = 304, description = "Not modified")
+        ),
+        security(("bearer" = []))
+    )]
     pub async fn get_one(
         Path(id): Path<i64>,
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
-    ) -> Result<Json<response::Project>> {
+        conditional: Conditional,
+    ) -> Result<Cached<Json<response::Project>>> {
+        let mut conn = db::acquire(&pool).await?;
+
         let project = sqlx::query_as!(
             response::Project,
             "SELECT id, name, target, description, created_at, updated_at
             FROM projects
-            WHERE id = $1 AND user_id = $2",
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
             id,
             user_id,
         )
-        .fetch_one(&pool)
-        .await?;
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("project `{id}` not found")))?;
 
-        Ok(Json(project))
+        Ok(conditional.row(project.id, project.updated_at, Json(project)))
     }
 
+    #[utoipa::path(
+        delete,
+        path = "/{id}",
+        tag = "projects",
+        params(("id" = i64, Path, description = "Project id")),
+        responses((status = 200, description = "Project moved to trash")),
+        security(("bearer" = []))
+    )]
     pub async fn delete(
         Path(id): Path<i64>,
         State(pool): State<PgPool>,
         AuthUser(user_id): AuthUser,
     ) -> Result<()> {
-        sqlx::query!(
-            "DELETE FROM projects WHERE id = $1 AND user_id = $2",
+        let mut conn = db::acquire(&pool).await?;
+
+        let result = sqlx::query!(
+            "UPDATE projects SET deleted_at = current_timestamp WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
             id,
             user_id,
         )
-        .execute(&pool)
+        .execute(&mut *conn)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!("project `{id}` not found")));
+        }
+
         Ok(())
     }
+
+    #[utoipa::path(
+        get,
+        path = "/trash",
+        tag = "projects",
+        responses((status = 200, description = "Projects in trash", body = Vec<response::DeletedProject>)),
+        security(("bearer" = []))
+    )]
+    pub async fn get_trash(
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+    ) -> Result<Json<Vec<response::DeletedProject>>> {
+        let mut conn = db::acquire(&pool).await?;
+
+        let projects = sqlx::query_as!(
+            response::DeletedProject,
+            r#"SELECT id, name, target, description, deleted_at AS "deleted_at!"
+            FROM projects
+            WHERE user_id = $1 AND deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC"#,
+            user_id,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(Json(projects))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/{id}/restore",
+        tag = "projects",
+        params(("id" = i64, Path, description = "Project id")),
+        responses((status = 200, description = "Project restored from trash")),
+        security(("bearer" = []))
+    )]
+    pub async fn restore(
+        Path(id): Path<i64>,
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+    ) -> Result<()> {
+        let mut conn = db::acquire(&pool).await?;
+
+        let result = sqlx::query!(
+            "UPDATE projects SET deleted_at = NULL WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL",
+            id,
+            user_id,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!(
+                "project `{id}` not found in trash"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) async fn check_owner<'e, E>(project_id: i64, user_id: i64, executor: E) -> Result<()>
+where
+    E: PgExecutor<'e>,
+{
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM projects WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+        ) AS "exists!""#,
+        project_id,
+        user_id,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    if !exists {
+        return Err(Error::NotFound(format!("project `{project_id}` not found")));
+    }
+
+    Ok(())
 }
@@ -0,0 +1,135 @@
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(paths(handler::search))]
+pub(crate) struct ApiDoc;
+
+pub(crate) mod router {
+    use axum::routing::{self, get};
+    use sqlx::{Pool, Postgres};
+
+    use super::handler;
+
+    pub fn new(pool: &Pool<Postgres>) -> routing::Router {
+        routing::Router::new()
+            .route("/", get(handler::search))
+            .with_state(pool.clone())
+    }
+}
+
+mod handler {
+    use axum::{extract::State, Json};
+    use sqlx::PgPool;
+    use validator::Validate;
+
+    use crate::api::extract::{AuthUser, Query};
+    use crate::api::{db, Result};
+
+    mod request {
+        use serde::Deserialize;
+        use utoipa::IntoParams;
+        use validator::Validate;
+
+        #[derive(Deserialize, Validate, IntoParams)]
+        #[into_params(parameter_in = Query)]
+        pub struct Search {
+            #[validate(length(min = 1))]
+            pub q: String,
+            pub project_id: Option<i64>,
+            pub target: Option<i16>,
+            #[validate(range(min = 1, max = 100))]
+            pub limit: Option<i64>,
+        }
+    }
+
+    mod response {
+        use serde::Serialize;
+        use utoipa::ToSchema;
+
+        #[derive(Serialize, ToSchema)]
+        #[schema(as = search::SearchResult)]
+        pub struct SearchResult {
+            pub kind: String,
+            pub id: i64,
+            pub project_id: i64,
+            pub name: String,
+            /// HTML-escaped text with matches wrapped in `<mark>` tags.
+            pub snippet: String,
+            pub rank: f32,
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/",
+        tag = "search",
+        params(request::Search),
+        responses((status = 200, description = "Matches ordered by rank", body = Vec<response::SearchResult>)),
+        security(("bearer" = []))
+    )]
+    pub async fn search(
+        State(pool): State<PgPool>,
+        AuthUser(user_id): AuthUser,
+        Query(params): Query<request::Search>,
+    ) -> Result<Json<Vec<response::SearchResult>>> {
+        params.validate()?;
+
+        let mut conn = db::acquire(&pool).await?;
+
+        let results = sqlx::query_as!(
+            response::SearchResult,
+            r#"WITH query AS (SELECT websearch_to_tsquery('simple', $1) AS q)
+            SELECT kind AS "kind!", id AS "id!", project_id AS "project_id!", name AS "name!",
+                snippet AS "snippet!", rank AS "rank!"
+            FROM (
+                SELECT 'project' AS kind, p.id, p.id AS project_id, p.name,
+                    -- Text is escaped first, so only the `<mark>` tags are markup.
+                    ts_headline(
+                        'simple',
+                        replace(replace(replace(replace(replace(p.name || ' ' || p.description,
+                            '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;'),
+                        query.q,
+                        'StartSel=<mark>, StopSel=</mark>, MaxFragments=2'
+                    ) AS snippet,
+                    ts_rank(p.search_vector, query.q) AS rank
+                FROM projects p
+                CROSS JOIN query
+                WHERE p.user_id = $2
+                    AND p.deleted_at IS NULL
+                    AND p.search_vector @@ query.q
+                    AND ($3::int8 IS NULL OR p.id = $3)
+                    AND ($4::int2 IS NULL OR p.target = $4)
+                UNION ALL
+                SELECT 'module', m.id, m.project_id, m.name,
+                    ts_headline(
+                        'simple',
+                        replace(replace(replace(replace(replace(m.name,
+                            '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;'),
+                        query.q,
+                        'StartSel=<mark>, StopSel=</mark>, MaxFragments=2'
+                    ),
+                    ts_rank(m.search_vector, query.q)
+                FROM modules m
+                JOIN projects p ON p.id = m.project_id
+                CROSS JOIN query
+                WHERE p.user_id = $2
+                    AND p.deleted_at IS NULL
+                    AND m.deleted_at IS NULL
+                    AND m.search_vector @@ query.q
+                    AND ($3::int8 IS NULL OR p.id = $3)
+                    AND ($4::int2 IS NULL OR p.target = $4)
+            ) results
+            ORDER BY rank DESC, kind DESC, id
+            LIMIT $5"#,
+            params.q,
+            user_id,
+            params.project_id,
+            params.target,
+            params.limit.unwrap_or(20),
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(Json(results))
+    }
+}
@@ -1,50 +1,219 @@
 use axum::{
-    http::StatusCode,
+    extract::rejection::JsonRejection,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use http_body_util::LengthLimitError;
+use serde::Serialize;
+use sqlx::error::ErrorKind;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::middleware::request_id;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error(transparent)]
-    DatabaseError(#[from] sqlx::error::Error),
+    DatabaseError(sqlx::error::Error),
     #[error(transparent)]
     ValidationError(#[from] validator::ValidationErrors),
     #[error(transparent)]
-    JsonRejection(#[from] axum::extract::rejection::JsonRejection),
+    JsonRejection(#[from] JsonRejection),
     #[error("{0}")]
     Unauthorized(String),
     #[error("{0}")]
     NotFound(String),
     #[error("Resource already exists")]
     Conflict,
+    /// A conflicting request is still being processed.
+    #[error("{0}")]
+    InProgress(String),
+    /// The resource is still referenced by others.
+    #[error("{0}")]
+    InUse(String),
     #[error("{0}")]
     BadRequest(String),
     #[error("{0}")]
+    UnprocessableEntity(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    /// Seconds until the client may retry.
+    #[error("rate limit exceeded, retry in {0} seconds")]
+    TooManyRequests(u64),
+    #[error("{0}")]
     InternalServerError(String),
 }
 
+impl From<sqlx::error::Error> for Error {
+    fn from(err: sqlx::error::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => Self::NotFound("resource not found".to_string()),
+            sqlx::Error::Database(database_error) => match database_error.kind() {
+                ErrorKind::UniqueViolation => Self::Conflict,
+                ErrorKind::ForeignKeyViolation => {
+                    Self::UnprocessableEntity("referenced resource does not exist".to_string())
+                }
+                ErrorKind::CheckViolation => {
+                    Self::BadRequest("value violates a constraint".to_string())
+                }
+                _ => Self::DatabaseError(err),
+            },
+            _ => Self::DatabaseError(err),
+        }
+    }
+}
+
+/// RFC 7807 problem details body.
+#[derive(Serialize, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    code: &'static str,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FieldError {
+    field: String,
+    code: String,
+    message: String,
+}
+
+impl Error {
+    /// Stable machine-readable identifier of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::DatabaseError(_) => "database_error",
+            Self::ValidationError(_) => "validation_error",
+            Self::JsonRejection(err) if is_too_large(err) => "payload_too_large",
+            Self::JsonRejection(_) => "invalid_json",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::NotFound(_) => "not_found",
+            Self::Conflict => "conflict",
+            Self::InProgress(_) => "in_progress",
+            Self::InUse(_) => "in_use",
+            Self::BadRequest(_) => "bad_request",
+            Self::UnprocessableEntity(_) => "unprocessable_entity",
+            Self::PayloadTooLarge(_) => "payload_too_large",
+            Self::TooManyRequests(_) => "too_many_requests",
+            Self::InternalServerError(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::JsonRejection(err) if is_too_large(err) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::JsonRejection(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::InProgress(_) => StatusCode::CONFLICT,
+            Self::InUse(_) => StatusCode::CONFLICT,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Whether the body was rejected by a length limit, possibly applied below other body wrappers.
+fn is_too_large(err: &JsonRejection) -> bool {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return true;
+    }
+
+    let mut source = std::error::Error::source(err);
+
+    while let Some(err) = source {
+        if err.is::<LengthLimitError>() {
+            return true;
+        }
+
+        source = err.source();
+    }
+
+    false
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            Self::DatabaseError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
-            Self::ValidationError(_) => {
-                let message = format!("Input validation error: [{self}]").replace('\n', ", ");
-                (StatusCode::BAD_REQUEST, message)
+        let status = self.status();
+        let code = self.code();
+        let request_id = request_id::current();
+        let retry_after = match self {
+            Self::TooManyRequests(secs) => Some(secs),
+            _ => None,
+        };
+
+        let (detail, errors) = match self {
+            Self::DatabaseError(err) => {
+                tracing::error!(request_id, "database error: {err}");
+                ("Internal server error".to_string(), Vec::new())
             }
-            Self::JsonRejection(err) => (StatusCode::BAD_REQUEST, err.to_string()),
-            Self::Unauthorized(err) => (StatusCode::UNAUTHORIZED, err),
-            Self::NotFound(err) => (StatusCode::NOT_FOUND, err),
-            Self::Conflict => (StatusCode::CONFLICT, Self::Conflict.to_string()),
-            Self::BadRequest(err) => (StatusCode::BAD_REQUEST, err),
-            Self::InternalServerError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err),
+            Self::InternalServerError(err) => {
+                tracing::error!(request_id, "internal error: {err}");
+                ("Internal server error".to_string(), Vec::new())
+            }
+            Self::ValidationError(err) => {
+                let mut errors = err
+                    .field_errors()
+                    .into_iter()
+                    .flat_map(|(field, errors)| {
+                        errors.iter().map(move |error| FieldError {
+                            field: field.to_string(),
+                            code: error.code.to_string(),
+                            message: error
+                                .message
+                                .as_ref()
+                                .map(|message| message.to_string())
+                                .unwrap_or_else(|| format!("invalid {field}: {}", error.code)),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                errors.sort_by(|a, b| a.field.cmp(&b.field));
+
+                ("Input validation error".to_string(), errors)
+            }
+            Self::JsonRejection(err) => (err.body_text(), Vec::new()),
+            err => (err.to_string(), Vec::new()),
         };
 
-        let body = Json(message);
+        let problem = Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            code,
+            detail,
+            request_id,
+            errors,
+        };
+
+        let mut res = (status, Json(problem)).into_response();
+
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+
+        if let Some(secs) = retry_after {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
 
-        (status, body).into_response()
+        res
     }
 }
@@ -0,0 +1,186 @@
+use std::{convert::Infallible, time::SystemTime};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use time::OffsetDateTime;
+
+use super::{list_query::Listed, Page};
+
+/// Validators of the copy a client has cached, sent as `If-None-Match` and
+/// `If-Modified-Since`.
+pub struct Conditional {
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+}
+
+impl<S> FromRequestParts<S> for Conditional
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            if_none_match: parts.headers.typed_get(),
+            if_modified_since: parts.headers.typed_get(),
+        })
+    }
+}
+
+impl Conditional {
+    /// Responds with a single row, versioned by its id and `updated_at`.
+    pub fn row<T>(self, id: i64, updated_at: OffsetDateTime, body: T) -> Cached<T> {
+        let etag = format!("W/\"{id:x}-{:x}\"", updated_at.unix_timestamp_nanos());
+
+        self.cached(etag, Some(updated_at.into()), body)
+    }
+
+    /// Responds with a page of rows. Removing a row does not make the page newer, so it only
+    /// gets an `ETag` and no `Last-Modified`.
+    pub fn page<T: Listed>(self, page: Page<T>) -> Cached<Page<T>> {
+        let etag = format!("W/\"{:x}\"", page.version());
+
+        self.cached(etag, None, page)
+    }
+
+    fn cached<T>(self, etag: String, last_modified: Option<SystemTime>, body: T) -> Cached<T> {
+        let etag = etag.parse::<ETag>().ok();
+
+        // `If-Modified-Since` is only checked without `If-None-Match`.
+        let not_modified = match (&self.if_none_match, &etag) {
+            (Some(if_none_match), Some(etag)) => !if_none_match.precondition_passes(etag),
+            (Some(_), None) => false,
+            (None, _) => self
+                .if_modified_since
+                .zip(last_modified)
+                .is_some_and(|(since, modified)| !since.is_modified(modified)),
+        };
+
+        Cached {
+            body: (!not_modified).then_some(body),
+            etag,
+            last_modified: last_modified.map(LastModified::from),
+        }
+    }
+}
+
+/// Response with validators, or `304 Not Modified` without a body if the client's copy is
+/// current.
+pub struct Cached<T> {
+    body: Option<T>,
+    etag: Option<ETag>,
+    last_modified: Option<LastModified>,
+}
+
+impl<T: IntoResponse> IntoResponse for Cached<T> {
+    fn into_response(self) -> Response {
+        let mut res = match self.body {
+            Some(body) => body.into_response(),
+            None => StatusCode::NOT_MODIFIED.into_response(),
+        };
+
+        let headers = res.headers_mut();
+
+        if let Some(etag) = self.etag {
+            headers.typed_insert(etag);
+        }
+
+        if let Some(last_modified) = self.last_modified {
+            headers.typed_insert(last_modified);
+        }
+
+        // Cached copies may be reused only after checking they are still current.
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use axum::http::{HeaderMap, HeaderName};
+
+    use super::*;
+
+    const ETAG: &str = "W/\"2a-1\"";
+    const MODIFIED: &str = "Thu, 01 Jan 2026 00:00:00 GMT";
+    const BEFORE: &str = "Wed, 31 Dec 2025 23:59:59 GMT";
+
+    fn conditional(headers: &[(HeaderName, &str)]) -> Conditional {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect::<HeaderMap>();
+
+        Conditional {
+            if_none_match: headers.typed_get(),
+            if_modified_since: headers.typed_get(),
+        }
+    }
+
+    #[test]
+    fn cached() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_767_225_600);
+
+        let cases = [
+            (vec![], StatusCode::OK),
+            (
+                vec![(header::IF_NONE_MATCH, ETAG)],
+                StatusCode::NOT_MODIFIED,
+            ),
+            (
+                vec![(header::IF_NONE_MATCH, "\"2a-1\"")],
+                StatusCode::NOT_MODIFIED,
+            ),
+            (vec![(header::IF_NONE_MATCH, "*")], StatusCode::NOT_MODIFIED),
+            (vec![(header::IF_NONE_MATCH, "W/\"2a-0\"")], StatusCode::OK),
+            (
+                vec![(header::IF_MODIFIED_SINCE, MODIFIED)],
+                StatusCode::NOT_MODIFIED,
+            ),
+            (vec![(header::IF_MODIFIED_SINCE, BEFORE)], StatusCode::OK),
+            // `If-None-Match` takes precedence over `If-Modified-Since`.
+            (
+                vec![
+                    (header::IF_NONE_MATCH, "W/\"2a-0\""),
+                    (header::IF_MODIFIED_SINCE, MODIFIED),
+                ],
+                StatusCode::OK,
+            ),
+            (
+                vec![
+                    (header::IF_NONE_MATCH, ETAG),
+                    (header::IF_MODIFIED_SINCE, BEFORE),
+                ],
+                StatusCode::NOT_MODIFIED,
+            ),
+        ];
+
+        for (headers, status) in cases {
+            let res = conditional(&headers)
+                .cached(ETAG.to_string(), Some(modified), "body")
+                .into_response();
+
+            assert_eq!(res.status(), status, "with {headers:?}");
+            assert_eq!(res.headers()[header::ETAG], ETAG);
+            assert_eq!(res.headers()[header::LAST_MODIFIED], MODIFIED);
+            assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+        }
+    }
+
+    #[test]
+    fn cached_without_last_modified() {
+        let res = conditional(&[(header::IF_MODIFIED_SINCE, MODIFIED)])
+            .cached(ETAG.to_string(), None, "body")
+            .into_response();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::LAST_MODIFIED));
+    }
+}
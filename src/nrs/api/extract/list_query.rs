@@ -0,0 +1,399 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::{
+    extract::{FromRequestParts, OriginalUri},
+    http::{header, request::Parts, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use time::OffsetDateTime;
+use utoipa::IntoParams;
+use validator::Validate;
+
+use super::Query;
+use crate::api;
+
+const DEFAULT_LIMIT: i64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Name,
+    Created,
+    Updated,
+}
+
+impl SortField {
+    fn column(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Created => "created_at",
+            Self::Updated => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl Sort {
+    fn parse(value: &str) -> Option<Self> {
+        let (descending, field) = match value.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, value),
+        };
+
+        let field = match field {
+            "name" => SortField::Name,
+            "created" => SortField::Created,
+            "updated" => SortField::Updated,
+            _ => return None,
+        };
+
+        Some(Self { field, descending })
+    }
+
+    fn as_str(self) -> String {
+        let field = match self.field {
+            SortField::Name => "name",
+            SortField::Created => "created",
+            SortField::Updated => "updated",
+        };
+
+        if self.descending {
+            format!("-{field}")
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            field: SortField::Updated,
+            descending: true,
+        }
+    }
+}
+
+/// Value of the sorted column of a row, used to continue listing after it.
+#[derive(Debug, Clone)]
+pub enum SortValue {
+    Text(String),
+    Time(OffsetDateTime),
+}
+
+/// Row of a list endpoint that can be paginated with [`ListQuery`].
+pub trait Listed {
+    fn id(&self) -> i64;
+    fn sort_value(&self, field: SortField) -> SortValue;
+}
+
+#[derive(Debug, Clone)]
+struct Cursor {
+    sort: Sort,
+    value: SortValue,
+    id: i64,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let value = match &self.value {
+            SortValue::Text(text) => text.clone(),
+            SortValue::Time(time) => time.unix_timestamp_nanos().to_string(),
+        };
+
+        URL_SAFE_NO_PAD.encode(format!("{}:{}:{value}", self.sort.as_str(), self.id))
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let cursor = String::from_utf8(bytes).ok()?;
+        let mut parts = cursor.splitn(3, ':');
+
+        let sort = Sort::parse(parts.next()?)?;
+        let id = parts.next()?.parse().ok()?;
+        let value = parts.next()?;
+
+        let value = match sort.field {
+            SortField::Name => SortValue::Text(value.to_string()),
+            SortField::Created | SortField::Updated => SortValue::Time(
+                OffsetDateTime::from_unix_timestamp_nanos(value.parse().ok()?).ok()?,
+            ),
+        };
+
+        Some(Self { sort, value, id })
+    }
+}
+
+#[derive(Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    /// Maximum number of items in a page, 50 by default.
+    #[validate(range(min = 1, max = 100))]
+    limit: Option<i64>,
+    /// Opaque position taken from the `Link` header of the previous page.
+    cursor: Option<String>,
+    /// `name`, `created` or `updated`, prefixed with `-` for descending order.
+    sort: Option<String>,
+    name_prefix: Option<String>,
+    target: Option<i16>,
+    updated_since: Option<DateTime<Utc>>,
+}
+
+/// Keyset pagination, sorting and filtering parameters shared by list endpoints.
+///
+/// Accepts `limit`, `cursor`, `sort` (`name`, `created` or `updated`, prefixed with `-`
+/// for descending order), `name_prefix`, `target` and `updated_since` query parameters.
+pub struct ListQuery {
+    pub limit: i64,
+    pub sort: Sort,
+    pub name_prefix: Option<String>,
+    pub target: Option<i16>,
+    pub updated_since: Option<DateTime<Utc>>,
+    cursor: Option<Cursor>,
+    uri: Uri,
+}
+
+impl<S> FromRequestParts<S> for ListQuery
+where
+    S: Send + Sync,
+{
+    type Rejection = api::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<ListParams>::from_request_parts(parts, state).await?;
+
+        params.validate()?;
+
+        let sort = match params.sort {
+            Some(sort) => Sort::parse(&sort)
+                .ok_or_else(|| api::Error::BadRequest(format!("invalid sort `{sort}`")))?,
+            None => Sort::default(),
+        };
+
+        let cursor = match params.cursor {
+            Some(cursor) => {
+                let cursor = Cursor::decode(&cursor)
+                    .ok_or_else(|| api::Error::BadRequest("invalid cursor".to_string()))?;
+
+                if cursor.sort != sort {
+                    return Err(api::Error::BadRequest(
+                        "cursor does not match sort".to_string(),
+                    ));
+                }
+
+                Some(cursor)
+            }
+            None => None,
+        };
+
+        let uri = match parts.extensions.get::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri.clone(),
+            None => parts.uri.clone(),
+        };
+
+        Ok(Self {
+            limit: params.limit.unwrap_or(DEFAULT_LIMIT),
+            sort,
+            name_prefix: params.name_prefix,
+            target: params.target,
+            updated_since: params.updated_since,
+            cursor,
+            uri,
+        })
+    }
+}
+
+impl ListQuery {
+    /// Appends ` AND ...` conditions for the common filters and the cursor position.
+    pub fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        if let Some(name_prefix) = &self.name_prefix {
+            query.push(" AND starts_with(name, ");
+            query.push_bind(name_prefix.clone());
+            query.push(")");
+        }
+
+        if let Some(updated_since) = self.updated_since {
+            query.push(" AND updated_at >= ");
+            query.push_bind(updated_since);
+        }
+
+        if let Some(cursor) = &self.cursor {
+            let operator = if self.sort.descending { "<" } else { ">" };
+
+            query.push(format!(
+                " AND ({}, id) {operator} (",
+                self.sort.field.column()
+            ));
+
+            match &cursor.value {
+                SortValue::Text(text) => query.push_bind(text.clone()),
+                SortValue::Time(time) => query.push_bind(*time),
+            };
+
+            query.push(", ");
+            query.push_bind(cursor.id);
+            query.push(")");
+        }
+    }
+
+    /// Appends `ORDER BY` and `LIMIT` clauses, fetching one extra row to detect the next page.
+    pub fn push_order(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        let direction = if self.sort.descending { "DESC" } else { "ASC" };
+
+        query.push(format!(
+            " ORDER BY {} {direction}, id {direction} LIMIT ",
+            self.sort.field.column()
+        ));
+        query.push_bind(self.limit + 1);
+    }
+
+    pub fn page<T: Listed>(&self, mut items: Vec<T>) -> Page<T> {
+        let mut next = None;
+
+        if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+
+            next = items.last().map(|item| {
+                let cursor = Cursor {
+                    sort: self.sort,
+                    value: item.sort_value(self.sort.field),
+                    id: item.id(),
+                };
+
+                self.link(&cursor.encode())
+            });
+        }
+
+        Page { items, next }
+    }
+
+    fn link(&self, cursor: &str) -> String {
+        let mut query = self
+            .uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty() && !pair.starts_with("cursor="))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        if !query.is_empty() {
+            query.push('&');
+        }
+
+        format!("{}?{query}cursor={cursor}", self.uri.path())
+    }
+}
+
+/// One page of a list endpoint, serialized as a JSON array with a `Link` header to the next page.
+pub struct Page<T> {
+    items: Vec<T>,
+    next: Option<String>,
+}
+
+impl<T: Listed> Page<T> {
+    /// Hash of the rows and their `updated_at`, changing whenever the page content does.
+    pub fn version(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        for item in &self.items {
+            item.id().hash(&mut hasher);
+
+            match item.sort_value(SortField::Updated) {
+                SortValue::Time(time) => time.unix_timestamp_nanos().hash(&mut hasher),
+                SortValue::Text(text) => text.hash(&mut hasher),
+            }
+        }
+
+        self.next.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> Response {
+        let mut response = Json(self.items).into_response();
+
+        if let Some(next) = self.next
+            && let Ok(link) = HeaderValue::from_str(&format!("<{next}>; rel=\"next\""))
+        {
+            response.headers_mut().insert(header::LINK, link);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursors = [
+            Cursor {
+                sort: Sort::default(),
+                value: SortValue::Time(
+                    OffsetDateTime::from_unix_timestamp_nanos(1_234_567_891).unwrap(),
+                ),
+                id: 42,
+            },
+            Cursor {
+                sort: Sort {
+                    field: SortField::Name,
+                    descending: false,
+                },
+                value: SortValue::Text("Module:with:colons é".to_string()),
+                id: 7,
+            },
+            Cursor {
+                sort: Sort {
+                    field: SortField::Created,
+                    descending: false,
+                },
+                value: SortValue::Time(OffsetDateTime::UNIX_EPOCH),
+                id: -1,
+            },
+        ];
+
+        for cursor in cursors {
+            let decoded = Cursor::decode(&cursor.encode()).expect("cursor decodes");
+
+            assert_eq!(decoded.sort, cursor.sort);
+            assert_eq!(decoded.id, cursor.id);
+
+            match (decoded.value, cursor.value) {
+                (SortValue::Text(decoded), SortValue::Text(value)) => assert_eq!(decoded, value),
+                (SortValue::Time(decoded), SortValue::Time(value)) => assert_eq!(decoded, value),
+                (decoded, value) => panic!("decoded {decoded:?} from {value:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_cursor() {
+        let cursors = [
+            "",
+            "not base64!",
+            &URL_SAFE_NO_PAD.encode([0xff, 0xfe]),
+            &URL_SAFE_NO_PAD.encode("-updated:42"),
+            &URL_SAFE_NO_PAD.encode("size:42:1"),
+            &URL_SAFE_NO_PAD.encode("name:abc:x"),
+            &URL_SAFE_NO_PAD.encode("-updated:42:yesterday"),
+            &URL_SAFE_NO_PAD.encode(format!("created:1:{}", i128::MAX)),
+        ];
+
+        for cursor in cursors {
+            assert!(Cursor::decode(cursor).is_none(), "decoded {cursor:?}");
+        }
+    }
+}
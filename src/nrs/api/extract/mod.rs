@@ -1,5 +1,13 @@
 pub mod auth_user;
+pub mod conditional;
+pub mod list_query;
+pub mod path;
+pub mod query;
 pub mod valid_payload;
 
 pub use auth_user::AuthUser;
+pub use conditional::{Cached, Conditional};
+pub use list_query::{ListQuery, Listed, Page};
+pub use path::Path;
+pub use query::Query;
 pub use valid_payload::ValidPayload;
@@ -0,0 +1,31 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::de::DeserializeOwned;
+
+use crate::api;
+
+/// [`axum::extract::Path`] answering malformed parameters with a problem document.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = api::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                // Missing parameters are a routing mistake, not the client's.
+                if e.status().is_server_error() {
+                    api::Error::InternalServerError(e.body_text())
+                } else {
+                    api::Error::BadRequest(e.body_text())
+                }
+            })?;
+
+        Ok(Path(value))
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json};
+use serde::Serialize;
+use sqlx::{PgPool, Pool, Postgres};
+
+use crate::core::migration;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Readiness switch shared with the server, turned off when it starts shutting down.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness {
+    draining: Arc<AtomicBool>,
+}
+
+impl Readiness {
+    pub fn set_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+struct HealthState {
+    pool: PgPool,
+    readiness: Readiness,
+}
+
+/// Serves `GET /healthz` and `GET /readyz` for the orchestrator.
+pub fn router(pool: &Pool<Postgres>, readiness: Readiness) -> axum::Router {
+    axum::Router::new()
+        .route("/healthz", get(health))
+        .route("/readyz", get(ready))
+        .with_state(HealthState {
+            pool: pool.clone(),
+            readiness,
+        })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Up,
+    Down,
+    Draining,
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: Status,
+}
+
+#[derive(Serialize)]
+struct ReadinessReport {
+    status: Status,
+    components: Components,
+}
+
+#[derive(Serialize)]
+struct Components {
+    database: Component,
+    migrations: Component,
+}
+
+#[derive(Serialize)]
+struct Component {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected_version: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    applied_version: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Component {
+    fn down(error: String) -> Self {
+        Self {
+            status: Status::Down,
+            latency_ms: None,
+            expected_version: None,
+            applied_version: None,
+            error: Some(error),
+        }
+    }
+
+    fn is_up(&self) -> bool {
+        matches!(self.status, Status::Up)
+    }
+}
+
+/// The process is up as long as it answers.
+async fn health() -> Json<Health> {
+    Json(Health { status: Status::Up })
+}
+
+/// Ready when the database answers and its schema is at least at the version this binary
+/// expects, unless the server is shutting down.
+async fn ready(State(state): State<HealthState>) -> impl IntoResponse {
+    let pool = state.pool;
+    let start = Instant::now();
+
+    let database =
+        match tokio::time::timeout(CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(&pool)).await {
+            Ok(Ok(_)) => Component {
+                status: Status::Up,
+                latency_ms: Some(start.elapsed().as_millis()),
+                expected_version: None,
+                applied_version: None,
+                error: None,
+            },
+            Ok(Err(err)) => {
+                tracing::error!("readiness database check failed: {err}");
+                Component::down("database query failed".to_string())
+            }
+            Err(_) => Component::down(format!("no answer in {CHECK_TIMEOUT:?}")),
+        };
+
+    let migrations = if database.is_up() {
+        check_migrations(&pool).await
+    } else {
+        Component::down("database is down".to_string())
+    };
+
+    let draining = state.readiness.is_draining();
+    let ready = !draining && database.is_up() && migrations.is_up();
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let report = ReadinessReport {
+        status: if draining {
+            Status::Draining
+        } else if ready {
+            Status::Up
+        } else {
+            Status::Down
+        },
+        components: Components {
+            database,
+            migrations,
+        },
+    };
+
+    (status, Json(report))
+}
+
+async fn check_migrations(pool: &PgPool) -> Component {
+    let expected = migration::expected_version();
+
+    let applied = match tokio::time::timeout(CHECK_TIMEOUT, migration::applied_version(pool)).await
+    {
+        Ok(Ok(applied)) => applied,
+        Ok(Err(err)) => {
+            tracing::error!("readiness migration check failed: {err}");
+            return Component::down("cannot read applied migrations".to_string());
+        }
+        Err(_) => return Component::down(format!("no answer in {CHECK_TIMEOUT:?}")),
+    };
+
+    // A newer schema comes from a newer replica of a rolling deploy, migrations are
+    // expected to stay compatible with the previous release.
+    let up = applied >= expected;
+
+    Component {
+        status: if up { Status::Up } else { Status::Down },
+        latency_ms: None,
+        expected_version: expected,
+        applied_version: applied,
+        error: (!up).then(|| "schema is older than expected".to_string()),
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::{PgPool, Pool, Postgres};
+
+use super::Result;
+
+/// Latency buckets in seconds.
+const BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Counting rows scans the tables, so the counts are refreshed at most this often.
+const COUNTS_INTERVAL: Duration = Duration::from_secs(60);
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the global Prometheus recorder on first call.
+pub fn install() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), BUCKETS)
+                .expect("buckets are not empty")
+                .install_recorder()
+                .expect("metrics recorder is installed once")
+        })
+        .clone()
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    pool: PgPool,
+    handle: PrometheusHandle,
+    /// When the project and module counts were last refreshed.
+    counted: Arc<Mutex<Option<Instant>>>,
+}
+
+/// Serves `GET /metrics` in Prometheus text format.
+pub fn router(pool: &Pool<Postgres>) -> axum::Router {
+    axum::Router::new()
+        .route("/metrics", get(handler))
+        .with_state(MetricsState {
+            pool: pool.clone(),
+            handle: install(),
+            counted: Arc::default(),
+        })
+}
+
+async fn handler(State(state): State<MetricsState>) -> Result<impl IntoResponse> {
+    let pool = &state.pool;
+
+    // Waits for connections are recorded as handlers acquire them, see [`super::db`].
+    metrics::gauge!("db_pool_connections").set(pool.size() as f64);
+    metrics::gauge!("db_pool_idle_connections").set(pool.num_idle() as f64);
+
+    let due = {
+        let mut counted = state.counted.lock().unwrap_or_else(|err| err.into_inner());
+        let due = counted.is_none_or(|counted| counted.elapsed() >= COUNTS_INTERVAL);
+
+        if due {
+            *counted = Some(Instant::now());
+        }
+
+        due
+    };
+
+    if due && let Err(err) = count(pool).await {
+        // Retried on the next scrape.
+        *state.counted.lock().unwrap_or_else(|err| err.into_inner()) = None;
+        return Err(err);
+    }
+
+    state.handle.run_upkeep();
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.handle.render(),
+    ))
+}
+
+async fn count(pool: &PgPool) -> Result<()> {
+    let projects = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM projects WHERE deleted_at IS NULL"#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let modules =
+        sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM modules WHERE deleted_at IS NULL"#)
+            .fetch_one(pool)
+            .await?;
+
+    metrics::gauge!("nrs_projects").set(projects as f64);
+    metrics::gauge!("nrs_modules").set(modules as f64);
+
+    Ok(())
+}
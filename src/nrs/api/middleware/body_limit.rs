@@ -0,0 +1,31 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+
+use crate::api::Error;
+
+/// Rejects requests with bodies larger than `limit` bytes with 413.
+///
+/// A declared `Content-Length` is checked upfront, other bodies fail while being read.
+pub async fn body_limit(State(limit): State<usize>, req: Request, next: Next) -> Response {
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    if content_length.is_some_and(|length| length > limit) {
+        return Error::PayloadTooLarge(format!("request body exceeds {limit} bytes"))
+            .into_response();
+    }
+
+    let (parts, body) = req.into_parts();
+    let req = Request::from_parts(parts, Body::new(Limited::new(body, limit)));
+
+    next.run(req).await
+}
@@ -1,44 +1,413 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
 use axum::body::{Body, Bytes};
-use axum::http::{Request, Response, StatusCode};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue};
 use axum::middleware::Next;
-use axum::response::IntoResponse;
-use http_body_util::BodyExt;
+use axum::response::Response;
+use http_body::{Frame, SizeHint};
+use tracing::Span;
+
+/// Fields redacted by default, compared case-insensitively.
+pub const DEFAULT_REDACT_FIELDS: [&str; 6] = [
+    "password",
+    "old_password",
+    "new_password",
+    "token",
+    "secret",
+    "jwt_secret",
+];
+
+const REDACTED: &str = "\"[REDACTED]\"";
+
+/// Settings of request and response body logging.
+#[derive(Debug, Clone)]
+pub struct BodyLog {
+    pub enabled: bool,
+    /// Names of JSON and form fields whose values are replaced with `[REDACTED]`.
+    pub redact_fields: Vec<String>,
+    /// Only this many leading bytes of a body are kept for the log.
+    pub max_size: usize,
+}
+
+impl Default for BodyLog {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            redact_fields: DEFAULT_REDACT_FIELDS
+                .iter()
+                .map(|field| field.to_string())
+                .collect(),
+            max_size: 4096,
+        }
+    }
+}
+
+/// Marker set on responses of routes which bodies must not be logged.
+#[derive(Debug, Clone, Copy)]
+pub struct SkipBodyLog;
+
+/// Opts a route out of body logging, applied with `route_layer` or `MethodRouter::layer`.
+pub async fn skip_body_log(req: Request, next: Next) -> Response {
+    let mut res = next.run(req).await;
+    res.extensions_mut().insert(SkipBodyLog);
+    res
+}
+
+/// Logs a bounded prefix of request and response bodies while streaming them through.
+///
+/// The request body is logged after the route is handled, since only then it is known
+/// whether the route opted out with [`skip_body_log`]. The response body is logged once
+/// it has been sent or dropped.
+pub async fn log_body(State(config): State<Arc<BodyLog>>, req: Request, next: Next) -> Response {
+    if !config.enabled {
+        return next.run(req).await;
+    }
 
-pub async fn log_body(
-    req: Request<axum::body::Body>,
-    next: Next,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
     let (parts, body) = req.into_parts();
-    let bytes = buffer_and_print("request", body).await?;
+    let content_type = parts.headers.get(header::CONTENT_TYPE).cloned();
+    let prefix = Arc::new(Mutex::new(Prefix::new(config.max_size)));
+
+    let body = TeeBody {
+        inner: body,
+        prefix: prefix.clone(),
+        log: None,
+    };
+
+    let res = next.run(Request::from_parts(parts, Body::new(body))).await;
+
+    if res.extensions().get::<SkipBodyLog>().is_some() {
+        return res;
+    }
+
+    if let Ok(prefix) = prefix.lock()
+        && let Some(body) = config.render(content_type.as_ref(), &prefix)
+    {
+        tracing::info!("request body = {body}");
+    }
 
-    let req = Request::from_parts(parts, Body::from(bytes));
-    let res = next.run(req).await;
     let (parts, body) = res.into_parts();
-    let bytes = buffer_and_print("response", body).await?;
+    let content_type = parts.headers.get(header::CONTENT_TYPE).cloned();
+
+    let body = TeeBody {
+        inner: body,
+        prefix: Arc::new(Mutex::new(Prefix::new(config.max_size))),
+        log: Some(ResponseLog {
+            config,
+            content_type,
+            span: Span::current(),
+        }),
+    };
+
+    Response::from_parts(parts, Body::new(body))
+}
+
+/// Leading bytes of a body along with its total size seen so far.
+struct Prefix {
+    bytes: Vec<u8>,
+    total: usize,
+    limit: usize,
+}
+
+impl Prefix {
+    fn new(limit: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            total: 0,
+            limit,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        let room = self.limit.saturating_sub(self.bytes.len());
+        self.bytes.extend_from_slice(&data[..room.min(data.len())]);
+        self.total += data.len();
+    }
+}
+
+struct ResponseLog {
+    config: Arc<BodyLog>,
+    content_type: Option<HeaderValue>,
+    /// Span of the request, the body is dropped outside of it.
+    span: Span,
+}
+
+/// Body passing frames through unchanged while copying the prefix of their data.
+struct TeeBody {
+    inner: Body,
+    prefix: Arc<Mutex<Prefix>>,
+    log: Option<ResponseLog>,
+}
+
+impl http_body::Body for TeeBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+            && let Ok(mut prefix) = self.prefix.lock()
+        {
+            prefix.push(data);
+        }
+
+        poll
+    }
 
-    let res = Response::from_parts(parts, Body::from(bytes));
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
 
-    Ok(res)
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
 }
 
-async fn buffer_and_print<B>(direction: &str, body: B) -> Result<Bytes, (StatusCode, String)>
-where
-    B: axum::body::HttpBody<Data = Bytes>,
-    B::Error: std::fmt::Display,
-{
-    let bytes = match body.collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(err) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                format!("failed to read {direction} body: {err}"),
-            ));
+impl Drop for TeeBody {
+    fn drop(&mut self) {
+        if let Some(log) = &self.log
+            && let Ok(prefix) = self.prefix.lock()
+            && let Some(body) = log.config.render(log.content_type.as_ref(), &prefix)
+        {
+            let _span = log.span.enter();
+            tracing::info!("response body = {body}");
         }
-    };
+    }
+}
 
-    if let Ok(body) = std::str::from_utf8(&bytes) {
-        tracing::info!("{direction} body = {body}");
+impl BodyLog {
+    /// Returns a loggable form of the body prefix, `None` if the body is empty.
+    fn render(&self, content_type: Option<&HeaderValue>, prefix: &Prefix) -> Option<String> {
+        if prefix.total == 0 {
+            return None;
+        }
+
+        let mime = content_type
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        let truncated = prefix.total > prefix.bytes.len();
+
+        // A truncated prefix may end in the middle of a character.
+        let text = match std::str::from_utf8(&prefix.bytes) {
+            Ok(text) => Some(text),
+            Err(err) if truncated && err.error_len().is_none() => {
+                std::str::from_utf8(&prefix.bytes[..err.valid_up_to()]).ok()
+            }
+            Err(_) => None,
+        };
+
+        let mut body = match text {
+            Some(text) if mime == "application/json" || mime.ends_with("+json") => {
+                self.redact_json(text)
+            }
+            Some(text) if mime == "application/x-www-form-urlencoded" => self.redact_form(text),
+            Some(text) if mime.starts_with("text/") => text.to_string(),
+            _ => {
+                let mime = if mime.is_empty() {
+                    "unknown type"
+                } else {
+                    &mime
+                };
+                return Some(format!("<{} bytes of {mime}>", prefix.total));
+            }
+        };
+
+        if truncated {
+            body.push_str(&format!("... ({} bytes total)", prefix.total));
+        }
+
+        Some(body)
+    }
+
+    fn is_redacted(&self, field: &str) -> bool {
+        self.redact_fields
+            .iter()
+            .any(|redacted| redacted.eq_ignore_ascii_case(field))
+    }
+
+    /// Replaces values of redacted fields, tolerating JSON cut off at any point.
+    fn redact_json(&self, json: &str) -> String {
+        let bytes = json.as_bytes();
+        let mut result = String::with_capacity(json.len());
+        let mut key: Option<String> = None;
+        let mut redact_value = false;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let c = bytes[i];
+
+            if c.is_ascii_whitespace() {
+                result.push(c as char);
+                i += 1;
+                continue;
+            }
+
+            if redact_value {
+                i = match c {
+                    b'"' => string_end(bytes, i),
+                    b'{' | b'[' => compound_end(bytes, i),
+                    _ => scalar_end(bytes, i),
+                };
+
+                result.push_str(REDACTED);
+                redact_value = false;
+                continue;
+            }
+
+            match c {
+                b'"' => {
+                    let end = string_end(bytes, i);
+                    result.push_str(&json[i..end]);
+                    key = Some(unescape(&json[i..end]));
+                    i = end;
+                }
+                b':' => {
+                    result.push(':');
+                    redact_value = key.take().is_some_and(|key| self.is_redacted(&key));
+                    i += 1;
+                }
+                _ => {
+                    let end = scalar_end(bytes, i).max(i + 1);
+                    result.push_str(&json[i..end]);
+                    key = None;
+                    i = end;
+                }
+            }
+        }
+
+        result
+    }
+
+    fn redact_form(&self, form: &str) -> String {
+        form.split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if self.is_redacted(key) => format!("{key}=[REDACTED]"),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+/// Value of a string token, so escaped keys like `"pass\u0077ord"` are matched too.
+fn unescape(token: &str) -> String {
+    serde_json::from_str(token).unwrap_or_else(|_| token.trim_matches('"').to_string())
+}
+
+/// Index after the closing quote of the string starting at `start`.
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+/// Index after the bracket closing the object or array starting at `start`.
+fn compound_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = string_end(bytes, i);
+                continue;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+
+        i += 1;
     }
 
-    Ok(bytes)
+    bytes.len()
+}
+
+/// Index of the delimiter ending the number or literal starting at `start`.
+fn scalar_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+
+    while i < bytes.len()
+        && !bytes[i].is_ascii_whitespace()
+        && !matches!(bytes[i], b',' | b':' | b'"' | b'{' | b'}' | b'[' | b']')
+    {
+        i += 1;
+    }
+
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_json() {
+        let config = BodyLog::default();
+
+        let cases = [
+            (
+                r#"{"login":"a","password":"x"}"#,
+                r#"{"login":"a","password":"[REDACTED]"}"#,
+            ),
+            (r#"{"Password": 12}"#, r#"{"Password": "[REDACTED]"}"#),
+            (
+                r#"{"pass\u0077ord":"x","\u0074oken":"y"}"#,
+                r#"{"pass\u0077ord":"[REDACTED]","\u0074oken":"[REDACTED]"}"#,
+            ),
+            (
+                r#"{"user":{"name":"n","token":"t"}}"#,
+                r#"{"user":{"name":"n","token":"[REDACTED]"}}"#,
+            ),
+            (
+                r#"[{"secret":"a"},{"secret":"b"}]"#,
+                r#"[{"secret":"[REDACTED]"},{"secret":"[REDACTED]"}]"#,
+            ),
+            (
+                r#"{"secret":{"a":["}",{"b":"]"}]},"name":"n"}"#,
+                r#"{"secret":"[REDACTED]","name":"n"}"#,
+            ),
+            (
+                r#"{"note":"{\"password\":\"x\"}","name":"a\"b","token":"t"}"#,
+                r#"{"note":"{\"password\":\"x\"}","name":"a\"b","token":"[REDACTED]"}"#,
+            ),
+            (
+                r#"{"name":"n","password":"abc"#,
+                r#"{"name":"n","password":"[REDACTED]""#,
+            ),
+            (r#"{"token":{"a":[1,2"#, r#"{"token":"[REDACTED]""#),
+            (r#"{"password":"#, r#"{"password":"#),
+            (r#"{"passw"#, r#"{"passw"#),
+        ];
+
+        for (json, expected) in cases {
+            assert_eq!(config.redact_json(json), expected, "redacting {json}");
+        }
+    }
 }
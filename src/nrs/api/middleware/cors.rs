@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method, Uri};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+const WILDCARD: &str = "*";
+
+/// Cross-origin settings for browser clients, parsed up front so building the layer
+/// cannot fail.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// `None` allows any origin.
+    origins: Option<Vec<HeaderValue>>,
+    /// `None` allows the method requested by the preflight.
+    methods: Option<Vec<Method>>,
+    /// `None` allows the headers requested by the preflight.
+    headers: Option<Vec<HeaderName>>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Each list may be `*` to allow anything. Requested headers are echoed back for `*`,
+    /// since a literal `*` does not cover `Authorization`.
+    pub fn new(
+        origins: &[String],
+        methods: &[String],
+        headers: &[String],
+        credentials: bool,
+        max_age: Option<Duration>,
+    ) -> Result<Self, String> {
+        let origins = wildcard_or(origins, |origin| {
+            let uri = origin.parse::<Uri>().ok();
+            let valid = uri.is_some_and(|uri| {
+                uri.scheme().is_some()
+                    && uri.authority().is_some()
+                    && uri.path_and_query().is_none_or(|path| path == "/")
+            }) && !origin.ends_with('/');
+
+            if !valid {
+                return Err(format!(
+                    "invalid CORS origin `{origin}`, expected `scheme://host[:port]`"
+                ));
+            }
+
+            HeaderValue::from_str(origin).map_err(|_| format!("invalid CORS origin `{origin}`"))
+        })?;
+
+        let methods = wildcard_or(methods, |method| {
+            Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|_| format!("invalid CORS method `{method}`"))
+        })?;
+
+        let headers = wildcard_or(headers, |header| {
+            HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| format!("invalid CORS header `{header}`"))
+        })?;
+
+        if credentials && origins.is_none() {
+            return Err("CORS credentials cannot be allowed for any origin".to_string());
+        }
+
+        Ok(Self {
+            origins,
+            methods,
+            headers,
+            credentials,
+            max_age,
+        })
+    }
+
+    pub fn layer(&self) -> CorsLayer {
+        let layer = CorsLayer::new()
+            .allow_origin(match &self.origins {
+                Some(origins) => AllowOrigin::list(origins.clone()),
+                None => AllowOrigin::any(),
+            })
+            .allow_methods(match &self.methods {
+                Some(methods) => AllowMethods::list(methods.clone()),
+                None => AllowMethods::mirror_request(),
+            })
+            .allow_headers(match &self.headers {
+                Some(headers) => AllowHeaders::list(headers.clone()),
+                None => AllowHeaders::mirror_request(),
+            })
+            .allow_credentials(self.credentials);
+
+        match self.max_age {
+            Some(max_age) => layer.max_age(max_age),
+            None => layer,
+        }
+    }
+}
+
+/// `None` for a `*` entry, otherwise every entry parsed.
+fn wildcard_or<T>(
+    values: &[String],
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<Option<Vec<T>>, String> {
+    if values.iter().any(|value| value.trim() == WILDCARD) {
+        return Ok(None);
+    }
+
+    values
+        .iter()
+        .map(|value| parse(value.trim()))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
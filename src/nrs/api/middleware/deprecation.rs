@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::{self, Next},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    /// When the routes were deprecated.
+    pub since: DateTime<Utc>,
+    /// When the routes are going to be removed.
+    pub sunset: Option<DateTime<Utc>>,
+    /// Path prefix of the replacing routes, linked as `successor-version`.
+    pub successor: Option<String>,
+}
+
+/// Marks all routes of `router` as deprecated with `Deprecation`, `Sunset` and `Link` headers.
+pub fn deprecate(router: axum::Router, deprecation: Deprecation) -> axum::Router {
+    router.layer(middleware::from_fn_with_state(
+        Arc::new(deprecation),
+        add_headers,
+    ))
+}
+
+async fn add_headers(
+    State(deprecation): State<Arc<Deprecation>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = req.uri().path().to_owned();
+    let mut res = next.run(req).await;
+    let headers = res.headers_mut();
+
+    if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecation.since.timestamp())) {
+        headers.insert(DEPRECATION.clone(), value);
+    }
+
+    if let Some(sunset) = deprecation.sunset {
+        let sunset = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+        if let Ok(value) = HeaderValue::from_str(&sunset) {
+            headers.insert(SUNSET.clone(), value);
+        }
+    }
+
+    if let Some(successor) = &deprecation.successor {
+        let link = format!("<{successor}{path}>; rel=\"successor-version\"");
+
+        if let Ok(value) = HeaderValue::from_str(&link) {
+            headers.append(header::LINK, value);
+        }
+    }
+
+    res
+}
@@ -0,0 +1,292 @@
+use axum::{
+    body::{self, Body},
+    extract::{FromRequestParts, OriginalUri, Request, State},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use http_body_util::LengthLimitError;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use super::request_id;
+use crate::api::{db, extract::AuthUser, Error, Result};
+
+static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+static IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+pub const DEFAULT_WINDOW_HOURS: u32 = 24;
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Keys of requests still in progress after this many seconds are given up, so a crash
+/// does not block retries for the whole window.
+const ABANDONED_SECS: f64 = 300.0;
+
+/// Hours during which a key replays the response of its first request.
+#[derive(Debug, Clone, Copy)]
+pub struct IdempotencyWindow(pub u32);
+
+struct Stored {
+    fingerprint: Vec<u8>,
+    status: Option<i16>,
+    content_type: Option<String>,
+    body: Option<Vec<u8>>,
+}
+
+/// Makes retries of a request with the same `Idempotency-Key` header replay the stored
+/// response of the first one instead of running the handler again.
+///
+/// Keys are scoped to the authenticated user, so requests with a key must be authenticated.
+/// Only successful responses are stored, failed requests can be retried with the same key.
+pub async fn idempotency(
+    State(pool): State<PgPool>,
+    Extension(IdempotencyWindow(window_hours)): Extension<IdempotencyWindow>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = req.headers().get(&IDEMPOTENCY_KEY) else {
+        return next.run(req).await;
+    };
+
+    let key = match parse_key(key) {
+        Ok(key) => key,
+        Err(err) => return err.into_response(),
+    };
+
+    let (mut parts, body) = req.into_parts();
+
+    // Anonymous callers would share one key space and could replay each other's responses.
+    let caller = match AuthUser::from_request_parts(&mut parts, &()).await {
+        Ok(AuthUser(user_id)) => format!("user:{user_id}"),
+        Err(_) => {
+            return Error::Unauthorized("`Idempotency-Key` requires authentication".to_string())
+                .into_response();
+        }
+    };
+
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => return read_error(err).into_response(),
+    };
+
+    // Nested routers strip their prefix from the URI, which would make the same body sent
+    // to different endpoints look like the same request.
+    let path = match parts.extensions.get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path(),
+        None => parts.uri.path(),
+    };
+
+    let fingerprint = Sha256::new()
+        .chain_update(parts.method.as_str())
+        .chain_update(b"\n")
+        .chain_update(path)
+        .chain_update(b"\n")
+        .chain_update(&body)
+        .finalize()
+        .to_vec();
+
+    match claim(&pool, &caller, &key, &fingerprint, window_hours).await {
+        Ok(None) => {}
+        Ok(Some(stored)) => return replay(stored, &fingerprint).into_response(),
+        Err(err) => return err.into_response(),
+    }
+
+    let req = Request::from_parts(parts, Body::from(body));
+
+    // Spawned so a client hanging up does not drop the handler between claiming the key and
+    // storing the response, which would block retries until the key is abandoned.
+    let handled = tokio::spawn(
+        request_id::inherit(async move {
+            let res = next.run(req).await;
+            store(&pool, &caller, &key, res).await
+        })
+        .instrument(tracing::Span::current()),
+    );
+
+    match handled.await {
+        Ok(res) => res,
+        Err(err) => {
+            Error::InternalServerError(format!("request handler failed: {err}")).into_response()
+        }
+    }
+}
+
+fn parse_key(value: &HeaderValue) -> Result<String> {
+    let key = value
+        .to_str()
+        .ok()
+        .map(|key| key.trim().trim_matches('"'))
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH);
+
+    key.map(str::to_owned).ok_or_else(|| {
+        Error::BadRequest(format!(
+            "`Idempotency-Key` must have 1 to {MAX_KEY_LENGTH} visible ASCII characters"
+        ))
+    })
+}
+
+fn read_error(err: axum::Error) -> Error {
+    let mut source = std::error::Error::source(&err);
+
+    while let Some(err) = source {
+        if err.is::<LengthLimitError>() {
+            return Error::PayloadTooLarge("request body is too large".to_string());
+        }
+
+        source = err.source();
+    }
+
+    Error::BadRequest(format!("cannot read request body: {err}"))
+}
+
+/// Reserves the key for this request, or returns what is stored for it by an earlier one.
+async fn claim(
+    pool: &PgPool,
+    caller: &str,
+    key: &str,
+    fingerprint: &[u8],
+    window_hours: u32,
+) -> Result<Option<Stored>> {
+    let window_hours = i32::try_from(window_hours).unwrap_or(i32::MAX);
+    let mut conn = db::acquire(pool).await?;
+
+    // Expired and abandoned keys are taken over as if they were new.
+    let claimed = sqlx::query_scalar!(
+        "INSERT INTO idempotency_keys (caller, key, fingerprint) VALUES ($1, $2, $3)
+        ON CONFLICT (caller, key) DO UPDATE
+        SET fingerprint = EXCLUDED.fingerprint, status = NULL, content_type = NULL, body = NULL,
+            created_at = current_timestamp
+        WHERE idempotency_keys.created_at < current_timestamp - make_interval(hours => $4)
+            OR (idempotency_keys.status IS NULL
+                AND idempotency_keys.created_at < current_timestamp - make_interval(secs => $5))
+        RETURNING true",
+        caller,
+        key,
+        fingerprint,
+        window_hours,
+        ABANDONED_SECS,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if claimed.is_some() {
+        return Ok(None);
+    }
+
+    let stored = sqlx::query_as!(
+        Stored,
+        "SELECT fingerprint, status, content_type, body FROM idempotency_keys
+        WHERE caller = $1 AND key = $2",
+        caller,
+        key,
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match stored {
+        Some(stored) => Ok(Some(stored)),
+        // Purged in between, the retry is not worth a loop.
+        None => Err(Error::InProgress(
+            "request with this `Idempotency-Key` is in progress, retry later".to_string(),
+        )),
+    }
+}
+
+fn replay(stored: Stored, fingerprint: &[u8]) -> Result<Response> {
+    if stored.fingerprint != fingerprint {
+        return Err(Error::UnprocessableEntity(
+            "`Idempotency-Key` was already used with a different request".to_string(),
+        ));
+    }
+
+    let Some(status) = stored.status else {
+        return Err(Error::InProgress(
+            "request with this `Idempotency-Key` is in progress, retry later".to_string(),
+        ));
+    };
+
+    let status = u16::try_from(status)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(|| Error::InternalServerError(format!("invalid stored status {status}")))?;
+
+    let mut res = (status, stored.body.unwrap_or_default()).into_response();
+    let headers = res.headers_mut();
+
+    match stored
+        .content_type
+        .and_then(|content_type| HeaderValue::from_str(&content_type).ok())
+    {
+        Some(content_type) => headers.insert(header::CONTENT_TYPE, content_type),
+        None => headers.remove(header::CONTENT_TYPE),
+    };
+
+    headers.insert(
+        IDEMPOTENT_REPLAYED.clone(),
+        HeaderValue::from_static("true"),
+    );
+
+    Ok(res)
+}
+
+/// Stores a successful response for replays, otherwise releases the key. The response is
+/// returned even if that fails, the handler has already run.
+async fn store(pool: &PgPool, caller: &str, key: &str, res: Response) -> Response {
+    if !res.status().is_success() {
+        let deleted = async {
+            sqlx::query!(
+                "DELETE FROM idempotency_keys WHERE caller = $1 AND key = $2",
+                caller,
+                key,
+            )
+            .execute(&mut *db::acquire(pool).await?)
+            .await
+        }
+        .await;
+
+        if let Err(err) = deleted {
+            tracing::error!("cannot release idempotency key: {err}");
+        }
+
+        return res;
+    }
+
+    let (parts, body) = res.into_parts();
+
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            return Error::InternalServerError(format!("cannot read response body: {err}"))
+                .into_response();
+        }
+    };
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok());
+
+    let stored = async {
+        sqlx::query!(
+            "UPDATE idempotency_keys SET status = $1, content_type = $2, body = $3
+            WHERE caller = $4 AND key = $5",
+            parts.status.as_u16() as i16,
+            content_type,
+            body.as_ref(),
+            caller,
+            key,
+        )
+        .execute(&mut *db::acquire(pool).await?)
+        .await
+    }
+    .await;
+
+    if let Err(err) = stored {
+        tracing::error!("cannot store idempotent response: {err}");
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
@@ -1 +1,9 @@
+pub mod body_limit;
 pub mod console;
+pub mod cors;
+pub mod deprecation;
+pub mod idempotency;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod telemetry;
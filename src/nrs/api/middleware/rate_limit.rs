@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{
+        connect_info::Connected, ConnectInfo, FromRequestParts, MatchedPath, Request, State,
+    },
+    http::{HeaderMap, HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    serve::IncomingStream,
+};
+use tokio::net::TcpListener;
+
+use crate::api::{extract::AuthUser, Error};
+
+static RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+static RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+static RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+static RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Budgets are per minute, buckets hold up to one minute of requests.
+const WINDOW_SECS: f64 = 60.0;
+
+/// Number of buckets above which full, idle ones are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Pruning walks all buckets, so it runs at most this often. Idle buckets are full again
+/// after one window anyway.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Peer address of a connection, absent on Unix sockets.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub Option<IpAddr>);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self(Some(stream.remote_addr().ip()))
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for ClientAddr {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self(None)
+    }
+}
+
+/// Requests per minute of each route class, 0 disables limiting the class.
+#[derive(Debug, Clone, Copy)]
+pub struct Budgets {
+    pub read: u32,
+    pub write: u32,
+    /// Login and account creation.
+    pub auth: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Read,
+    Write,
+    Auth,
+}
+
+impl Class {
+    fn of(method: &Method, route: &str) -> Self {
+        let route = route.trim_end_matches('/');
+
+        if route.ends_with("/account/login")
+            || (method == Method::POST && route.ends_with("/account"))
+        {
+            Self::Auth
+        } else if matches!(*method, Method::GET | Method::HEAD) {
+            Self::Read
+        } else {
+            Self::Write
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Client {
+    User(i64),
+    Ip(IpAddr),
+    /// Clients without an address share one bucket.
+    Unknown,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<(Client, Class), Bucket>,
+    pruned: Instant,
+}
+
+struct Decision {
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+    /// Seconds until the next request is allowed, if this one is rejected.
+    retry_after: Option<u64>,
+}
+
+/// Token buckets per client and route class, keyed by user id for authenticated requests
+/// and by client address otherwise.
+#[derive(Debug)]
+pub struct RateLimiter {
+    budgets: Budgets,
+    /// Use the address appended by a reverse proxy to `X-Forwarded-For`.
+    forwarded_for: bool,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(budgets: Budgets, forwarded_for: bool) -> Self {
+        Self {
+            budgets,
+            forwarded_for,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    fn budget(&self, class: Class) -> u32 {
+        match class {
+            Class::Read => self.budgets.read,
+            Class::Write => self.budgets.write,
+            Class::Auth => self.budgets.auth,
+        }
+    }
+
+    fn client_ip(&self, req: &Request) -> Option<IpAddr> {
+        if self.forwarded_for
+            && let Some(ip) = forwarded_for(req.headers())
+        {
+            return Some(ip);
+        }
+
+        req.extensions()
+            .get::<ConnectInfo<ClientAddr>>()
+            .and_then(|ConnectInfo(addr)| addr.0)
+    }
+
+    fn take(&self, client: Client, class: Class) -> Decision {
+        let limit = self.budget(class);
+        let capacity = f64::from(limit);
+        let rate = capacity / WINDOW_SECS;
+        let now = Instant::now();
+
+        let mut state = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        let Buckets { buckets, pruned } = &mut *state;
+
+        if buckets.len() > PRUNE_THRESHOLD && now.duration_since(*pruned) >= PRUNE_INTERVAL {
+            *pruned = now;
+            buckets.retain(|(_, class), bucket| {
+                let capacity = f64::from(self.budget(*class));
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+
+                bucket.tokens + elapsed * capacity / WINDOW_SECS < capacity
+            });
+        }
+
+        let bucket = buckets.entry((client, class)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        let retry_after = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        };
+
+        Decision {
+            limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after,
+        }
+    }
+}
+
+/// Rejects requests over the budget of their route class with 429 and reports the
+/// remaining budget in `RateLimit-*` headers.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let class = Class::of(req.method(), &route);
+
+    if limiter.budget(class) == 0 {
+        return next.run(req).await;
+    }
+
+    let ip = limiter.client_ip(&req);
+    let (mut parts, body) = req.into_parts();
+
+    // Invalid tokens are rejected by the handler, until then they count as anonymous.
+    let client = match AuthUser::from_request_parts(&mut parts, &()).await {
+        Ok(AuthUser(user_id)) => Client::User(user_id),
+        Err(_) => ip.map_or(Client::Unknown, Client::Ip),
+    };
+
+    let decision = limiter.take(client, class);
+
+    let mut res = match decision.retry_after {
+        Some(retry_after) => Error::TooManyRequests(retry_after).into_response(),
+        None => next.run(Request::from_parts(parts, body)).await,
+    };
+
+    let headers = res.headers_mut();
+    headers.insert(RATELIMIT_LIMIT.clone(), HeaderValue::from(decision.limit));
+    headers.insert(
+        RATELIMIT_REMAINING.clone(),
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(RATELIMIT_RESET.clone(), HeaderValue::from(decision.reset));
+
+    if let Ok(policy) = HeaderValue::from_str(&format!("{};w={WINDOW_SECS}", decision.limit)) {
+        headers.insert(RATELIMIT_POLICY.clone(), policy);
+    }
+
+    res
+}
+
+/// The last `X-Forwarded-For` address, the one appended by the closest proxy.
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all(&X_FORWARDED_FOR)
+        .iter()
+        .next_back()?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
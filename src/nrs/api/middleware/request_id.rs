@@ -0,0 +1,65 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::core::telemetry;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Returns the id of the request being handled on the current task.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Carries the id of the current request over to a future spawned on another task.
+pub fn inherit<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    let id = current();
+
+    async move {
+        match id {
+            Some(id) => REQUEST_ID.scope(id, f).await,
+            None => f.await,
+        }
+    }
+}
+
+/// Reuses a client supplied `X-Request-Id` or generates a new one and echoes it in the response.
+///
+/// The id is also set on the request for inner layers and recorded in a span around it.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let value = HeaderValue::from_str(&id).ok();
+
+    if let Some(value) = &value {
+        req.headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), value.clone());
+    }
+
+    let span = tracing::info_span!("request", request_id = %id);
+    telemetry::set_parent(&span, req.headers());
+    let mut res = REQUEST_ID.scope(id, next.run(req)).instrument(span).await;
+
+    if let Some(value) = value {
+        res.headers_mut().insert(REQUEST_ID_HEADER.clone(), value);
+    }
+
+    res
+}
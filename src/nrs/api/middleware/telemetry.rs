@@ -0,0 +1,23 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// Wraps the handler of a matched route in a span named after it, applied with `route_layer`.
+pub async fn handler_span(req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+
+    let span = tracing::info_span!(
+        "handler",
+        otel.name = %format!("{} {route}", req.method()),
+        http.route = %route,
+    );
+
+    next.run(req).instrument(span).await
+}
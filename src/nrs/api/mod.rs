@@ -1,7 +1,11 @@
+pub mod db;
 pub mod endpoint;
 pub mod error;
 pub mod extract;
+pub mod health;
+pub mod metrics;
 pub mod middleware;
+pub mod openapi;
 pub mod router;
 
 pub use error::Error;
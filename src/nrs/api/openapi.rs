@@ -0,0 +1,123 @@
+use axum::Json;
+use utoipa::{
+    openapi::{
+        self,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        ContentBuilder, Ref, ResponseBuilder,
+    },
+    Modify, OpenApi,
+};
+
+use super::{endpoint, error::Problem, router::API_PREFIX};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Norm repository server"),
+    paths(get),
+    components(schemas(Problem)),
+    modifiers(&BearerAuth)
+)]
+struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Builds the OpenAPI document of all endpoints mounted by [`super::router::Router`].
+pub fn spec() -> openapi::OpenApi {
+    let nested = [
+        ("/account", "account", endpoint::account::ApiDoc::openapi()),
+        ("/projects", "project", endpoint::project::ApiDoc::openapi()),
+        (
+            "/projects/{project_id}/modules",
+            "module",
+            endpoint::module::ApiDoc::openapi(),
+        ),
+        ("/search", "search", endpoint::search::ApiDoc::openapi()),
+    ];
+
+    let mut spec = ApiDoc::openapi();
+
+    for (path, prefix, mut api) in nested {
+        // Handlers share names like `get_all` across endpoints, operation ids must be unique.
+        for operation in operations(&mut api) {
+            if let Some(id) = &operation.operation_id {
+                operation.operation_id = Some(format!("{prefix}_{id}"));
+            }
+        }
+
+        spec = spec.nest_with_path_composer(format!("{API_PREFIX}{path}"), api, compose);
+    }
+
+    add_error_responses(&mut spec);
+
+    spec
+}
+
+fn operations(spec: &mut openapi::OpenApi) -> impl Iterator<Item = &mut openapi::path::Operation> {
+    spec.paths.paths.values_mut().flat_map(|item| {
+        [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.patch,
+        ]
+        .into_iter()
+        .flatten()
+    })
+}
+
+/// Joins paths the way `axum::Router::nest` does, so a nested `/` maps to the prefix itself.
+fn compose(base: &str, path: &str) -> String {
+    match path {
+        "/" => base.to_string(),
+        path => format!("{base}{path}"),
+    }
+}
+
+fn add_error_responses(spec: &mut openapi::OpenApi) {
+    for operation in operations(spec) {
+        for (status, description) in [("4XX", "Client error"), ("5XX", "Server error")] {
+            let response = ResponseBuilder::new()
+                .description(description)
+                .content(
+                    "application/problem+json",
+                    ContentBuilder::new()
+                        .schema(Some(Ref::from_schema_name("Problem")))
+                        .build(),
+                )
+                .build();
+
+            operation
+                .responses
+                .responses
+                .entry(status.to_string())
+                .or_insert(response.into());
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "meta",
+    responses((status = 200, description = "OpenAPI document of the API"))
+)]
+pub async fn get() -> Json<openapi::OpenApi> {
+    Json(spec())
+}
@@ -1,9 +1,42 @@
-use axum::{middleware, routing::IntoMakeService, Extension};
+use axum::{
+    extract::DefaultBodyLimit,
+    http::{header, HeaderValue},
+    middleware,
+    routing::{get, IntoMakeService},
+    Extension,
+};
+use chrono::{DateTime, Utc};
 use sqlx::{Pool, Postgres};
 use std::sync::Arc;
-use tower_http::trace::TraceLayer;
+use tower_http::{
+    compression::CompressionLayer, set_header::SetResponseHeaderLayer, trace::TraceLayer,
+};
 
-use super::{endpoint, middleware::console::log_body};
+use super::{
+    endpoint,
+    health::{self, Readiness},
+    metrics,
+    middleware::{
+        body_limit::body_limit,
+        console::{log_body, skip_body_log, BodyLog},
+        cors::Cors,
+        deprecation::{self, Deprecation},
+        idempotency::{self, IdempotencyWindow},
+        metrics::track,
+        rate_limit::{rate_limit, RateLimiter},
+        request_id::request_id,
+        telemetry::handler_span,
+    },
+    openapi,
+};
+
+/// Path prefix of the current API version.
+pub const API_PREFIX: &str = "/v1";
+
+pub const DEFAULT_MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// When the unversioned routes were deprecated by the introduction of [`API_PREFIX`].
+pub const UNVERSIONED_DEPRECATED_SINCE: &str = "2026-10-19T00:00:00Z";
 
 pub struct Router {
     axum_router: axum::Router,
@@ -13,24 +46,142 @@ pub struct JwtExt {
     pub secret: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub jwt_secret: String,
+    /// Also serve the current API without the version prefix, marked as deprecated.
+    pub unversioned_routes: bool,
+    pub unversioned_deprecated_since: DateTime<Utc>,
+    pub unversioned_sunset: Option<DateTime<Utc>>,
+    pub body_log: BodyLog,
+    /// Maximum size of a request body in bytes.
+    pub max_body_size: usize,
+    /// Serve `/metrics` on this router, otherwise it is expected on a separate admin port.
+    pub serve_metrics: bool,
+    pub readiness: Readiness,
+    /// `Strict-Transport-Security` max age, set when serving HTTPS.
+    pub hsts_max_age: Option<u64>,
+    /// Cross-origin requests are refused by browsers if unset.
+    pub cors: Option<Cors>,
+    /// Requests to the API are not limited if unset.
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    /// Compress responses with gzip, brotli or zstd as accepted by the client.
+    pub compression: bool,
+    /// Hours during which an `Idempotency-Key` replays the first response.
+    pub idempotency_window_hours: u32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            jwt_secret: String::new(),
+            unversioned_routes: false,
+            unversioned_deprecated_since: UNVERSIONED_DEPRECATED_SINCE.parse().unwrap_or_default(),
+            unversioned_sunset: None,
+            body_log: BodyLog::default(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            serve_metrics: false,
+            readiness: Readiness::default(),
+            hsts_max_age: None,
+            cors: None,
+            rate_limit: None,
+            compression: true,
+            idempotency_window_hours: idempotency::DEFAULT_WINDOW_HOURS,
+        }
+    }
+}
+
 impl Router {
-    pub fn new(pool: Pool<Postgres>, jwt_secret: &str) -> Self {
+    pub fn new(pool: Pool<Postgres>, options: Options) -> Self {
         let jwt_ext = Arc::new(JwtExt {
-            secret: jwt_secret.to_owned(),
+            secret: options.jwt_secret,
         });
 
-        let router = axum::Router::new()
+        let mut api = axum::Router::new()
             .nest("/account", endpoint::account::router::new(&pool))
             .nest("/projects", endpoint::project::router::new(&pool))
+            .nest("/search", endpoint::search::router::new(&pool));
+
+        // Both API versions share the buckets.
+        if let Some(limiter) = options.rate_limit {
+            api = api.route_layer(middleware::from_fn_with_state(limiter, rate_limit));
+        }
+
+        let mut router = axum::Router::new().nest(API_PREFIX, api.clone());
+
+        if options.unversioned_routes {
+            router = router.merge(deprecation::deprecate(
+                api,
+                Deprecation {
+                    since: options.unversioned_deprecated_since,
+                    sunset: options.unversioned_sunset,
+                    successor: Some(API_PREFIX.to_string()),
+                },
+            ));
+        }
+
+        if options.serve_metrics {
+            router = router.merge(metrics::router(&pool));
+        } else {
+            metrics::install();
+        }
+
+        let mut router = router
+            .merge(
+                health::router(&pool, options.readiness)
+                    .route_layer(middleware::from_fn(skip_body_log)),
+            )
+            .route(
+                "/openapi.json",
+                get(openapi::get).layer(middleware::from_fn(skip_body_log)),
+            )
+            .route_layer(middleware::from_fn(handler_span))
+            .route_layer(middleware::from_fn(track))
             .layer(TraceLayer::new_for_http())
             .layer(Extension(jwt_ext))
-            .layer(middleware::from_fn(log_body));
+            .layer(Extension(IdempotencyWindow(
+                options.idempotency_window_hours,
+            )))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(options.body_log),
+                log_body,
+            ))
+            // Extractors rely on `body_limit` instead of their own default of 2 MiB.
+            .layer(DefaultBodyLimit::disable())
+            .layer(middleware::from_fn_with_state(
+                options.max_body_size,
+                body_limit,
+            ))
+            .layer(middleware::from_fn(request_id));
+
+        // Outside body logging, which needs the uncompressed response.
+        if options.compression {
+            router = router.layer(CompressionLayer::new());
+        }
+
+        // Outside the body limit, so preflights are answered and rejections carry the headers.
+        if let Some(cors) = &options.cors {
+            router = router.layer(cors.layer());
+        }
+
+        if let Some(max_age) = options.hsts_max_age
+            && let Ok(value) = HeaderValue::from_str(&format!("max-age={max_age}"))
+        {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                header::STRICT_TRANSPORT_SECURITY,
+                value,
+            ));
+        }
 
         Self {
             axum_router: router,
         }
     }
 
+    pub fn into_inner(self) -> axum::Router {
+        self.axum_router
+    }
+
     pub fn into_make_service(self) -> IntoMakeService<axum::Router> {
         self.axum_router.into_make_service()
     }
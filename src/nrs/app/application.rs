@@ -1,67 +1,315 @@
-use std::{
-    error::Error,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+use std::{error::Error, fmt::Debug, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
 };
 
 use sqlx;
-use sqlx::postgres::PgPoolOptions;
-
-use clap::Parser;
-use tracing::info;
-use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
-
-use crate::api::router;
-
-#[derive(Parser, Debug)]
-pub struct Config {
-    #[clap(long, env)]
-    port: u16,
-    #[clap(long, env)]
-    database_url: String,
-    #[clap(long, env)]
-    rust_log: String,
-    #[clap(long, env)]
-    jwt_secret: String,
-}
+use sqlx::{postgres::PgPoolOptions, PgPool};
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{info, Level};
+use tracing_subscriber::{
+    filter::filter_fn, fmt, fmt::writer::BoxMakeWriter, layer::Layered,
+    prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+    Registry,
+};
+
+use crate::{
+    api::{
+        health::Readiness,
+        metrics,
+        middleware::{
+            console::BodyLog,
+            rate_limit::{Budgets, ClientAddr, RateLimiter},
+        },
+        router,
+    },
+    core::{idempotency, migration, telemetry, trash},
+};
+
+use super::{
+    cli::{self, Command},
+    config::{Config, LogFormat},
+    shutdown::{self, Shutdown},
+    tls::{self, Tls},
+};
+
+const QUERY_DIRECTIVE: &str = "sqlx::query=debug";
 
 pub struct Application {
     config: Config,
+    tracer_provider: Option<SdkTracerProvider>,
 }
 
 impl Application {
     pub fn new() -> Result<Self, Box<dyn Error>> {
-        let config = Config::parse();
+        let config = Config::load();
+
+        // Filters are global: per-layer filters get out of sync on `log` records which
+        // are checked with `enabled` but never emitted.
+        let mut filter = EnvFilter::new(&config.rust_log);
+        let mut layers: Vec<Box<dyn Layer<Layered<EnvFilter, Registry>> + Send + Sync>> =
+            Vec::new();
+
+        let tracer_provider = match &config.otlp_endpoint {
+            Some(endpoint) => {
+                let provider = telemetry::init(
+                    endpoint,
+                    config.otlp_sampler_ratio,
+                    &config.otel_service_name,
+                )?;
+                let tracer = provider.tracer("nrs");
+
+                layers.push(
+                    tracing_opentelemetry::layer()
+                        .with_tracer(tracer.clone())
+                        .boxed(),
+                );
+                layers.push(telemetry::QuerySpans::new(tracer).boxed());
+
+                // Queries are logged at debug level, they become spans regardless of `RUST_LOG`.
+                filter = filter.add_directive(QUERY_DIRECTIVE.parse()?);
+
+                Some(provider)
+            }
+            None => None,
+        };
+
+        // Admin commands print their results on stdout, so logs must not mix with them.
+        let writer = match config.command {
+            None | Some(Command::Serve) => BoxMakeWriter::new(std::io::stdout),
+            Some(_) => BoxMakeWriter::new(std::io::stderr),
+        };
+
+        let fmt_layer = match config.log_format {
+            LogFormat::Text => fmt::layer().with_writer(writer).boxed(),
+            LogFormat::Json => fmt::layer().json().with_writer(writer).boxed(),
+        };
+
+        if tracer_provider.is_some() && !logs_queries(&config.rust_log) {
+            layers.push(
+                fmt_layer
+                    .with_filter(filter_fn(|metadata| {
+                        metadata.target() != "sqlx::query" || *metadata.level() <= Level::INFO
+                    }))
+                    .boxed(),
+            );
+        } else {
+            layers.push(fmt_layer);
+        }
 
         tracing_subscriber::registry()
-            .with(tracing_subscriber::EnvFilter::new(&config.rust_log))
-            .with(tracing_subscriber::fmt::layer().without_time())
+            .with(filter)
+            .with(layers)
             .init();
 
-        Ok(Self { config })
+        Ok(Self {
+            config,
+            tracer_provider,
+        })
     }
 
+    /// Runs the command from the configuration, the server by default.
     pub async fn run(&self) -> Result<(), Box<dyn Error>> {
         let pool = PgPoolOptions::new()
-            .max_connections(5)
+            .max_connections(self.config.db_max_connections)
+            .min_connections(self.config.db_min_connections)
+            .acquire_timeout(Duration::from_secs(self.config.db_acquire_timeout))
+            .idle_timeout(optional_secs(self.config.db_idle_timeout))
+            .max_lifetime(optional_secs(self.config.db_max_lifetime))
             .connect(&self.config.database_url)
             .await?;
 
-        sqlx::migrate!().run(&pool).await?;
+        let result = match &self.config.command {
+            None | Some(Command::Serve) => self.serve(pool.clone()).await,
+            Some(Command::Admin(command)) => {
+                cli::run(command, &pool, self.config.jwt_secret.as_deref()).await
+            }
+        };
+
+        pool.close().await;
+        info!("Database pool closed");
+
+        if let Some(provider) = &self.tracer_provider {
+            provider.shutdown()?;
+        }
+
+        result
+    }
+
+    async fn serve(&self, pool: PgPool) -> Result<(), Box<dyn Error>> {
+        if self.config.auto_migrate {
+            for migration in migration::up(&pool, false).await? {
+                info!(
+                    "Applied migration {} {}",
+                    migration.version, migration.description
+                );
+            }
+        } else {
+            let pending = migration::pending(&pool).await?;
+
+            if !pending.is_empty() {
+                tracing::warn!(
+                    "{} migrations are pending, readiness fails until `nrs migrate up` is run",
+                    pending.len()
+                );
+            }
+        }
+
+        tokio::spawn(trash::run_purge(
+            pool.clone(),
+            self.config.trash_retention_days,
+        ));
+        tokio::spawn(idempotency::run_purge(
+            pool.clone(),
+            self.config.idempotency_window,
+        ));
+
+        let tls = match (&self.config.tls_cert, &self.config.tls_key) {
+            (Some(cert), Some(key)) => Some(Arc::new(Tls::load(cert, key)?)),
+            _ => None,
+        };
+
+        let readiness = Readiness::default();
+        let (stop, shutdown) = Shutdown::new();
 
-        info!(
-            "Norm repository server started on port {}",
-            self.config.port
+        let router = router::Router::new(
+            pool.clone(),
+            router::Options {
+                jwt_secret: self
+                    .config
+                    .jwt_secret
+                    .clone()
+                    .ok_or("`jwt_secret` is required to serve")?,
+                unversioned_routes: self.config.unversioned_routes,
+                unversioned_deprecated_since: self.config.unversioned_deprecated_since,
+                unversioned_sunset: self.config.unversioned_sunset,
+                body_log: BodyLog {
+                    enabled: self.config.log_bodies,
+                    redact_fields: self.config.log_redact_fields.clone(),
+                    max_size: self.config.log_body_max_size,
+                },
+                max_body_size: self.config.max_body_size,
+                serve_metrics: self.config.metrics_port.is_none(),
+                readiness: readiness.clone(),
+                hsts_max_age: tls
+                    .as_ref()
+                    .map(|_| self.config.hsts_max_age)
+                    .filter(|max_age| *max_age > 0),
+                cors: self.config.cors()?,
+                rate_limit: Some(Arc::new(RateLimiter::new(
+                    Budgets {
+                        read: self.config.rate_limit_read,
+                        write: self.config.rate_limit_write,
+                        auth: self.config.rate_limit_auth,
+                    },
+                    self.config.rate_limit_forwarded_for,
+                ))),
+                compression: self.config.compression,
+                idempotency_window_hours: self.config.idempotency_window,
+            },
         );
 
-        let router = router::Router::new(pool, &self.config.jwt_secret);
+        if let Some(port) = self.config.metrics_port {
+            let listener =
+                tokio::net::TcpListener::bind(SocketAddr::new(self.config.host, port)).await?;
 
-        let listener = tokio::net::TcpListener::bind(&SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
-            self.config.port,
-        ))
-        .await?;
+            info!("Metrics are served on {}", listener.local_addr()?);
 
-        axum::serve(listener, router.into_make_service()).await?;
+            let server = axum::serve(listener, metrics::router(&pool))
+                .with_graceful_shutdown(shutdown.clone().wait());
+
+            tokio::spawn(async move {
+                if let Err(err) = server.await {
+                    tracing::error!("metrics server failed: {err}");
+                }
+            });
+        }
+
+        if let Some(tls) = &tls {
+            tokio::spawn(tls.clone().watch(shutdown.clone()));
+        }
+
+        if let Some(port) = self.config.tls_redirect_port {
+            let listener =
+                tokio::net::TcpListener::bind(SocketAddr::new(self.config.host, port)).await?;
+
+            info!("Redirecting to HTTPS from {}", listener.local_addr()?);
+
+            let server = axum::serve(listener, tls::redirect_router(self.config.port))
+                .with_graceful_shutdown(shutdown.clone().wait());
+
+            tokio::spawn(async move {
+                if let Err(err) = server.await {
+                    tracing::error!("redirect server failed: {err}");
+                }
+            });
+        }
+
+        let delay = Duration::from_secs(self.config.shutdown_delay);
+        let timeout = Duration::from_secs(self.config.shutdown_timeout);
+
+        tokio::spawn(async move {
+            shutdown::signal().await;
+            info!("Shutting down, readiness is failing now");
+            readiness.set_draining();
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown::signal() => info!("Signaled again, skipping the shutdown delay"),
+            }
+
+            info!("Stopped accepting connections, draining in-flight requests");
+            let _ = stop.send(true);
+        });
+
+        match &self.config.unix_socket {
+            #[cfg(unix)]
+            Some(path) => {
+                use std::os::unix::fs::FileTypeExt;
+
+                // A socket file left by a previous run would fail the bind, anything else
+                // at that path is not ours to remove.
+                match std::fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+                    Ok(_) => return Err(format!("{} is not a socket", path.display()).into()),
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err.into()),
+                }
+
+                let listener = tokio::net::UnixListener::bind(path)?;
+                info!("Norm repository server started on {}", path.display());
+
+                serve(listener, router.into_inner(), shutdown, timeout).await?;
+                std::fs::remove_file(path)?;
+            }
+            #[cfg(not(unix))]
+            Some(_) => return Err("Unix sockets are not supported on this platform".into()),
+            None => {
+                let listener = tokio::net::TcpListener::bind(SocketAddr::new(
+                    self.config.host,
+                    self.config.port,
+                ))
+                .await?;
+
+                let addr = listener.local_addr()?;
+
+                match &tls {
+                    Some(tls) => {
+                        info!("Norm repository server started on https://{addr}");
+                        let listener = tls.listener(listener)?;
+                        serve(listener, router.into_inner(), shutdown, timeout).await?;
+                    }
+                    None => {
+                        info!("Norm repository server started on {addr}");
+                        serve(listener, router.into_inner(), shutdown, timeout).await?;
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
@@ -70,3 +318,50 @@ impl Application {
         &self.config
     }
 }
+
+/// Serves until shutdown, then waits up to `timeout` for in-flight requests.
+async fn serve<L>(
+    listener: L,
+    router: axum::Router,
+    shutdown: Shutdown,
+    timeout: Duration,
+) -> std::io::Result<()>
+where
+    L: Listener,
+    L::Addr: Debug,
+    for<'a> ClientAddr: Connected<IncomingStream<'a, L>>,
+{
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<ClientAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.clone().wait());
+
+    let deadline = async {
+        shutdown.wait().await;
+        tokio::time::sleep(timeout).await;
+    };
+
+    tokio::select! {
+        result = server => result,
+        _ = deadline => {
+            tracing::warn!("In-flight requests did not finish in {timeout:?}, aborting them");
+            Ok(())
+        }
+    }
+}
+
+/// Whether `RUST_LOG` asks for query statements, which sqlx logs at debug level.
+fn logs_queries(rust_log: &str) -> bool {
+    rust_log.split(',').any(|directive| {
+        let (target, level) = directive.trim().rsplit_once('=').unwrap_or(("", directive));
+        let verbose = matches!(level.trim(), "debug" | "trace");
+
+        verbose && (target.is_empty() || "sqlx::query".starts_with(target))
+    })
+}
+
+/// Zero turns a timeout off.
+fn optional_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
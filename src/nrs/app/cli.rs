@@ -0,0 +1,280 @@
+use std::{error::Error, io};
+
+use clap::{Args, Subcommand};
+use sqlx::PgPool;
+
+use crate::core::{jwt, migration};
+
+/// Role of the `Admins` user group, `Users` have role 1.
+const ADMIN_ROLE: i16 = 0;
+const USER_ROLE: i16 = 1;
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the server, the default without a command.
+    Serve,
+    #[command(flatten)]
+    Admin(AdminCommand),
+}
+
+/// Commands run against the database without starting the server.
+#[derive(Subcommand, Debug)]
+pub enum AdminCommand {
+    /// Apply, revert or list database migrations.
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manage user accounts.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manage projects.
+    #[command(subcommand)]
+    Project(ProjectCommand),
+    /// Manage API tokens.
+    #[command(subcommand)]
+    Token(TokenCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations.
+    Up {
+        /// List the pending migrations without applying them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Revert the latest applied migration, or all migrations newer than `--target`.
+    Down {
+        /// Version to revert to, 0 reverts everything.
+        #[arg(long)]
+        target: Option<i64>,
+        /// List the migrations which would be reverted without reverting them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List migrations and whether they are applied.
+    Status,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UserCommand {
+    /// Create an account and print its id.
+    Create {
+        #[arg(long)]
+        login: String,
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        full_name: String,
+        #[command(flatten)]
+        password: Password,
+        /// Add the account to the `Admins` group.
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Set a new password for an account.
+    ResetPassword {
+        /// Login or email of the account.
+        user: String,
+        #[command(flatten)]
+        password: Password,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProjectCommand {
+    /// Give a project to another account.
+    Transfer {
+        /// Project id.
+        project: i64,
+        /// Login or email of the new owner.
+        #[arg(long)]
+        to: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenCommand {
+    /// Print a new API token for an account.
+    Issue {
+        /// Login or email of the account.
+        user: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Password {
+    /// Read from standard input if not given, so it stays out of the shell history.
+    #[arg(long)]
+    password: Option<String>,
+}
+
+impl Password {
+    fn read(&self) -> io::Result<String> {
+        if let Some(password) = &self.password {
+            return Ok(password.clone());
+        }
+
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// Runs an admin command against the database, the server is not started.
+pub async fn run(
+    command: &AdminCommand,
+    pool: &PgPool,
+    jwt_secret: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        AdminCommand::Migrate(command) => migrate(command, pool).await,
+        AdminCommand::User(command) => user(command, pool).await,
+        AdminCommand::Project(ProjectCommand::Transfer { project, to }) => {
+            let owner = find_user(pool, to).await?;
+
+            let result = sqlx::query!(
+                "UPDATE projects SET user_id = $1, updated_at = current_timestamp WHERE id = $2",
+                owner.id,
+                project
+            )
+            .execute(pool)
+            .await?;
+
+            if result.rows_affected() == 0 {
+                return Err(format!("project {project} not found").into());
+            }
+
+            println!("Project {project} transferred to {}", owner.login);
+            Ok(())
+        }
+        AdminCommand::Token(TokenCommand::Issue { user }) => {
+            let jwt_secret = jwt_secret.ok_or("`jwt_secret` is required to issue tokens")?;
+            let user = find_user(pool, user).await?;
+            println!("{}", jwt::create_token(user.id, &user.email, jwt_secret)?);
+            Ok(())
+        }
+    }
+}
+
+async fn migrate(command: &MigrateCommand, pool: &PgPool) -> Result<(), Box<dyn Error>> {
+    match command {
+        MigrateCommand::Up { dry_run } => {
+            let applied = migration::up(pool, *dry_run).await?;
+            let verb = if *dry_run { "Would apply" } else { "Applied" };
+
+            for migration in &applied {
+                println!("{verb} {} {}", migration.version, migration.description);
+            }
+
+            if applied.is_empty() {
+                println!("Database is up to date");
+            }
+        }
+        MigrateCommand::Down { target, dry_run } => {
+            let reverted = migration::down(pool, *target, *dry_run).await?;
+            let verb = if *dry_run { "Would revert" } else { "Reverted" };
+
+            for migration in &reverted {
+                println!("{verb} {} {}", migration.version, migration.description);
+            }
+
+            if reverted.is_empty() {
+                println!("Nothing to revert");
+            }
+        }
+        MigrateCommand::Status => {
+            let applied = migration::applied(&mut *pool.acquire().await?).await?;
+
+            for migration in migration::MIGRATOR
+                .iter()
+                .filter(|m| m.migration_type.is_up_migration())
+            {
+                let status = match applied.get(&migration.version) {
+                    None => "pending",
+                    Some(checksum) if *checksum != *migration.checksum => "changed",
+                    Some(_) => "applied",
+                };
+
+                println!(
+                    "{:<14} {:<8} {}",
+                    migration.version, status, migration.description
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn user(command: &UserCommand, pool: &PgPool) -> Result<(), Box<dyn Error>> {
+    match command {
+        UserCommand::Create {
+            login,
+            email,
+            full_name,
+            password,
+            admin,
+        } => {
+            let password = password.read()?;
+
+            if password.is_empty() {
+                return Err("password must not be empty".into());
+            }
+
+            let role = if *admin { ADMIN_ROLE } else { USER_ROLE };
+
+            let id = sqlx::query_scalar!(
+                "INSERT INTO users (group_id, login, full_name, email, password)
+                 VALUES ((SELECT id FROM user_groups WHERE role = $1 ORDER BY id LIMIT 1), $2, $3, $4, $5)
+                 RETURNING id",
+                role,
+                login,
+                full_name,
+                email,
+                password
+            )
+            .fetch_one(pool)
+            .await?;
+
+            println!("{id}");
+        }
+        UserCommand::ResetPassword { user, password } => {
+            let user = find_user(pool, user).await?;
+            let password = password.read()?;
+
+            if password.is_empty() {
+                return Err("password must not be empty".into());
+            }
+
+            sqlx::query!(
+                "UPDATE users SET password = $1, updated_at = current_timestamp WHERE id = $2",
+                password,
+                user.id
+            )
+            .execute(pool)
+            .await?;
+
+            println!("Password of {} reset", user.login);
+        }
+    }
+
+    Ok(())
+}
+
+struct User {
+    id: i64,
+    login: String,
+    email: String,
+}
+
+async fn find_user(pool: &PgPool, login_or_email: &str) -> Result<User, Box<dyn Error>> {
+    sqlx::query_as!(
+        User,
+        "SELECT id, login, email FROM users WHERE login = $1 OR email = $1",
+        login_or_email
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| format!("user `{login_or_email}` not found").into())
+}
@@ -0,0 +1,468 @@
+use std::{
+    ffi::OsString,
+    fs,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum::http::Uri;
+use chrono::{DateTime, Utc};
+use clap::{error::ErrorKind, ArgAction, Command, CommandFactory, Parser, ValueEnum};
+use tracing_subscriber::EnvFilter;
+
+use crate::api::{
+    middleware::{console, cors::Cors, idempotency},
+    router,
+};
+
+use super::cli;
+
+const CONFIG_FLAG: &str = "--config";
+const CONFIG_ENV: &str = "NRS_CONFIG";
+const DEFAULT_CORS_METHODS: [&str; 5] = ["GET", "POST", "PUT", "DELETE", "HEAD"];
+const DEFAULT_CORS_HEADERS: [&str; 2] = ["authorization", "content-type"];
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per event with timestamps and span fields.
+    Json,
+}
+
+/// Command and settings taken from command line flags, then environment variables, then the
+/// config file, then defaults.
+#[derive(Parser, Debug)]
+#[command(args_override_self = true)]
+pub struct Config {
+    #[command(subcommand)]
+    pub(crate) command: Option<cli::Command>,
+    /// TOML file with any of these options, keyed by their flag names in snake case.
+    #[clap(long, env = CONFIG_ENV)]
+    pub(crate) config: Option<PathBuf>,
+    /// IPv4 or IPv6 address to listen on.
+    #[clap(long, env, default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
+    pub(crate) host: IpAddr,
+    #[clap(long, env, default_value_t = 3000)]
+    pub(crate) port: u16,
+    /// Listen on this Unix socket instead of `host` and `port`.
+    #[clap(long, env)]
+    pub(crate) unix_socket: Option<PathBuf>,
+    /// PEM certificate chain, enables HTTPS together with `tls_key`.
+    #[clap(long, env)]
+    pub(crate) tls_cert: Option<PathBuf>,
+    /// PEM private key, reloaded with the certificate on SIGHUP or file change.
+    #[clap(long, env)]
+    pub(crate) tls_key: Option<PathBuf>,
+    /// Port of a plain HTTP listener redirecting to HTTPS.
+    #[clap(long, env)]
+    pub(crate) tls_redirect_port: Option<u16>,
+    /// `Strict-Transport-Security` max age in seconds sent over HTTPS, 0 disables it.
+    #[clap(long, env, default_value_t = 31_536_000)]
+    pub(crate) hsts_max_age: u64,
+    #[clap(long, env)]
+    pub(crate) database_url: String,
+    /// Apply pending migrations on startup, otherwise run `nrs migrate up` before deploying.
+    #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+    pub(crate) auto_migrate: bool,
+    #[clap(long, env, default_value_t = 5)]
+    pub(crate) db_max_connections: u32,
+    #[clap(long, env, default_value_t = 0)]
+    pub(crate) db_min_connections: u32,
+    /// Seconds to wait for a free connection.
+    #[clap(long, env, default_value_t = 30)]
+    pub(crate) db_acquire_timeout: u64,
+    /// Seconds after which idle connections above the minimum are closed, 0 keeps them.
+    #[clap(long, env, default_value_t = 600)]
+    pub(crate) db_idle_timeout: u64,
+    /// Seconds after which connections are replaced, 0 keeps them.
+    #[clap(long, env, default_value_t = 1800)]
+    pub(crate) db_max_lifetime: u64,
+    /// Log filter, e.g. `info,sqlx=warn`.
+    #[clap(long, env, default_value = "info")]
+    pub(crate) rust_log: String,
+    #[clap(long, env, value_enum, default_value_t = LogFormat::Text)]
+    pub(crate) log_format: LogFormat,
+    /// Required to serve and to issue tokens.
+    #[clap(long, env)]
+    pub(crate) jwt_secret: Option<String>,
+    #[clap(long, env, default_value_t = 30)]
+    pub(crate) trash_retention_days: u32,
+    /// Serve the API without the `/v1` prefix too, as deprecated aliases.
+    #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+    pub(crate) unversioned_routes: bool,
+    /// Date announced in the `Deprecation` header of the unversioned aliases.
+    #[clap(long, env, default_value = router::UNVERSIONED_DEPRECATED_SINCE)]
+    pub(crate) unversioned_deprecated_since: DateTime<Utc>,
+    /// Announced removal date of the unversioned aliases.
+    #[clap(long, env)]
+    pub(crate) unversioned_sunset: Option<DateTime<Utc>>,
+    /// Log request and response bodies, should be disabled in production.
+    #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+    pub(crate) log_bodies: bool,
+    /// Comma-separated JSON and form fields redacted in logged bodies.
+    #[clap(long, env, value_delimiter = ',', default_values_t = console::DEFAULT_REDACT_FIELDS.map(String::from))]
+    pub(crate) log_redact_fields: Vec<String>,
+    /// Logged bodies are truncated to this number of bytes.
+    #[clap(long, env, default_value_t = 4096)]
+    pub(crate) log_body_max_size: usize,
+    /// Requests with larger bodies are rejected with 413.
+    #[clap(long, env, default_value_t = router::DEFAULT_MAX_BODY_SIZE)]
+    pub(crate) max_body_size: usize,
+    /// Comma-separated origins allowed to call the API from browsers, e.g.
+    /// `https://ide.example.com`, or `*` for any. CORS is disabled if empty.
+    #[clap(long, env, value_delimiter = ',')]
+    pub(crate) cors_allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests, or `*` for any.
+    #[clap(long, env, value_delimiter = ',', default_values_t = DEFAULT_CORS_METHODS.map(String::from))]
+    pub(crate) cors_allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests, or `*` for any.
+    #[clap(long, env, value_delimiter = ',', default_values_t = DEFAULT_CORS_HEADERS.map(String::from))]
+    pub(crate) cors_allowed_headers: Vec<String>,
+    /// Allow cookies and credentials in cross-origin requests, needs explicit origins.
+    #[clap(long, env, default_value_t = false, action = ArgAction::Set)]
+    pub(crate) cors_allow_credentials: bool,
+    /// Seconds browsers may cache preflight responses, 0 leaves it to the browser.
+    #[clap(long, env, default_value_t = 600)]
+    pub(crate) cors_max_age: u64,
+    /// Read requests per minute of a user or client address, 0 disables the limit.
+    #[clap(long, env, default_value_t = 600)]
+    pub(crate) rate_limit_read: u32,
+    /// Write requests per minute of a user or client address, 0 disables the limit.
+    #[clap(long, env, default_value_t = 120)]
+    pub(crate) rate_limit_write: u32,
+    /// Login and sign-up requests per minute of a client address, 0 disables the limit.
+    #[clap(long, env, default_value_t = 10)]
+    pub(crate) rate_limit_auth: u32,
+    /// Identify anonymous clients by the last `X-Forwarded-For` address, only enable
+    /// behind a reverse proxy which sets it.
+    #[clap(long, env, default_value_t = false, action = ArgAction::Set)]
+    pub(crate) rate_limit_forwarded_for: bool,
+    /// Hours during which retries with the same `Idempotency-Key` replay the first response.
+    #[clap(long, env, default_value_t = idempotency::DEFAULT_WINDOW_HOURS)]
+    pub(crate) idempotency_window: u32,
+    /// Compress responses for clients accepting gzip, brotli or zstd.
+    #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+    pub(crate) compression: bool,
+    /// Serve `/metrics` on a separate admin port of `host` instead of the main listener.
+    #[clap(long, env)]
+    pub(crate) metrics_port: Option<u16>,
+    /// OTLP/HTTP collector URL, e.g. `http://localhost:4318`, traces are not exported if unset.
+    #[clap(long, env)]
+    pub(crate) otlp_endpoint: Option<String>,
+    /// Fraction of new traces to sample, incoming `traceparent` decisions are kept.
+    #[clap(long, env, default_value_t = 1.0)]
+    pub(crate) otlp_sampler_ratio: f64,
+    #[clap(long, env, default_value = "nrs")]
+    pub(crate) otel_service_name: String,
+    /// Seconds between failing readiness and closing the listener on shutdown, longer than
+    /// the readiness probe period so load balancers stop routing first. A second signal
+    /// skips the delay.
+    #[clap(long, env, default_value_t = 15)]
+    pub(crate) shutdown_delay: u64,
+    /// Seconds to wait for in-flight requests before aborting them on shutdown.
+    #[clap(long, env, default_value_t = 30)]
+    pub(crate) shutdown_timeout: u64,
+}
+
+impl Config {
+    /// Parses the configuration, printing the error and exiting if it is invalid.
+    pub fn load() -> Self {
+        Self::try_load(std::env::args_os()).unwrap_or_else(|err| err.exit())
+    }
+
+    fn try_load(args: impl IntoIterator<Item = OsString>) -> Result<Self, clap::Error> {
+        let mut args = args.into_iter().collect::<Vec<_>>();
+
+        if let Some(path) = config_path(&args) {
+            let file_args = file_args(&Self::command(), &path)
+                .map_err(|err| Self::command().error(ErrorKind::InvalidValue, err))?;
+
+            // Flags from the file go first, so the command line overrides them.
+            args.splice(1..1, file_args);
+        }
+
+        let config = Self::try_parse_from(args)?;
+
+        config
+            .validate()
+            .map_err(|err| Self::command().error(ErrorKind::ValueValidation, err))?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let needs_secret = matches!(
+            self.command,
+            None | Some(cli::Command::Serve)
+                | Some(cli::Command::Admin(cli::AdminCommand::Token(_)))
+        );
+
+        match self.jwt_secret.as_deref() {
+            Some("") => return Err("`jwt_secret` must not be empty".to_string()),
+            None if needs_secret => {
+                return Err("`jwt_secret` is required to serve and issue tokens".to_string());
+            }
+            _ => {}
+        }
+
+        if self.db_max_connections == 0 {
+            return Err("`db_max_connections` must be at least 1".to_string());
+        }
+
+        if self.db_min_connections > self.db_max_connections {
+            return Err(format!(
+                "`db_min_connections` ({}) exceeds `db_max_connections` ({})",
+                self.db_min_connections, self.db_max_connections
+            ));
+        }
+
+        if let Err(err) = EnvFilter::try_new(&self.rust_log) {
+            return Err(format!("invalid `rust_log` `{}`: {err}", self.rust_log));
+        }
+
+        if self.idempotency_window == 0 {
+            return Err("`idempotency_window` must be at least 1 hour".to_string());
+        }
+
+        if self.max_body_size == 0 {
+            return Err("`max_body_size` must not be 0".to_string());
+        }
+
+        if self.unix_socket.is_none() && self.metrics_port == Some(self.port) {
+            return Err("`metrics_port` must differ from `port`".to_string());
+        }
+
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("`tls_cert` and `tls_key` must be set together".to_string());
+        }
+
+        if self.tls_cert.is_some() && self.unix_socket.is_some() {
+            return Err("TLS is not supported on a Unix socket".to_string());
+        }
+
+        if let Some(port) = self.tls_redirect_port {
+            if self.tls_cert.is_none() {
+                return Err("`tls_redirect_port` requires `tls_cert` and `tls_key`".to_string());
+            }
+
+            if port == self.port || Some(port) == self.metrics_port {
+                return Err("`tls_redirect_port` must differ from other ports".to_string());
+            }
+        }
+
+        if let Some(endpoint) = &self.otlp_endpoint {
+            let valid = endpoint
+                .parse::<Uri>()
+                .is_ok_and(|uri| uri.scheme().is_some() && uri.host().is_some());
+
+            if !valid {
+                return Err(format!("invalid `otlp_endpoint` `{endpoint}`"));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.otlp_sampler_ratio) {
+            return Err("`otlp_sampler_ratio` must be between 0 and 1".to_string());
+        }
+
+        self.cors()?;
+
+        Ok(())
+    }
+
+    /// Cross-origin settings, `None` if no origins are allowed.
+    pub(crate) fn cors(&self) -> Result<Option<Cors>, String> {
+        if self.cors_allowed_origins.is_empty() {
+            return Ok(None);
+        }
+
+        Cors::new(
+            &self.cors_allowed_origins,
+            &self.cors_allowed_methods,
+            &self.cors_allowed_headers,
+            self.cors_allow_credentials,
+            (self.cors_max_age > 0).then(|| Duration::from_secs(self.cors_max_age)),
+        )
+        .map(Some)
+    }
+}
+
+/// Finds the config file path in the arguments or the environment before full parsing.
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+
+        if arg == CONFIG_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+
+        if let Some(path) = arg.strip_prefix(&format!("{CONFIG_FLAG}=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    std::env::var_os(CONFIG_ENV).map(PathBuf::from)
+}
+
+/// Turns the config file into flags, skipping options set through the environment.
+fn file_args(command: &Command, path: &Path) -> Result<Vec<OsString>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read config file {}: {err}", path.display()))?;
+
+    let table = text
+        .parse::<toml::Table>()
+        .map_err(|err| format!("invalid config file {}: {err}", path.display()))?;
+
+    let mut args = Vec::new();
+
+    for (key, value) in table {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str() && arg.get_long().is_some())
+            .filter(|arg| arg.get_id() != "config")
+            .ok_or_else(|| format!("unknown option `{key}` in config file"))?;
+
+        if arg
+            .get_env()
+            .is_some_and(|env| std::env::var_os(env).is_some())
+        {
+            continue;
+        }
+
+        let value = match value {
+            toml::Value::Array(items) => items
+                .into_iter()
+                .map(|item| scalar(&key, item))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => scalar(&key, value)?,
+        };
+
+        let long = arg.get_long().unwrap_or_default();
+        args.push(OsString::from(format!("--{long}={value}")));
+    }
+
+    Ok(args)
+}
+
+fn scalar(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        toml::Value::Datetime(value) => Ok(value.to_string()),
+        _ => Err(format!(
+            "option `{key}` in config file must be a scalar or a list"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Mutex};
+
+    use super::*;
+
+    /// Parsing reads the whole environment, so tests changing it run one at a time.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Variables the tests set or expect unset.
+    const VARS: [&str; 4] = [
+        "PORT",
+        "DB_MAX_CONNECTIONS",
+        "CORS_ALLOWED_ORIGINS",
+        "LOG_BODIES",
+    ];
+
+    const REQUIRED: &str = "database_url = \"postgres://localhost/nrs\"\njwt_secret = \"secret\"\n";
+
+    fn load(file: &str, args: &[&str], env: &[(&str, &str)]) -> Result<Config, clap::Error> {
+        let _env = ENV.lock().unwrap_or_else(|err| err.into_inner());
+
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        write!(config, "{REQUIRED}{file}").unwrap();
+
+        let path = config.path().as_os_str().to_owned();
+        let args = [OsString::from("nrs"), OsString::from(CONFIG_FLAG), path]
+            .into_iter()
+            .chain(args.iter().map(OsString::from));
+
+        // SAFETY: other tests reading the environment wait for `ENV`.
+        unsafe {
+            VARS.iter().for_each(|key| std::env::remove_var(key));
+            env.iter()
+                .for_each(|(key, value)| std::env::set_var(key, value));
+        }
+
+        let loaded = Config::try_load(args);
+
+        // SAFETY: as above.
+        unsafe {
+            env.iter().for_each(|(key, _)| std::env::remove_var(key));
+        }
+
+        loaded
+    }
+
+    #[test]
+    fn file() {
+        let config = load(
+            "port = 4000\n\
+            db_max_connections = 7\n\
+            cors_allowed_origins = [\"https://a.example\", \"https://b.example\"]\n\
+            log_bodies = false\n",
+            &[],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.db_max_connections, 7);
+        assert_eq!(
+            config.cors_allowed_origins,
+            ["https://a.example", "https://b.example"]
+        );
+        assert!(!config.log_bodies);
+        assert_eq!(config.jwt_secret.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let config = load("port = 4000\n", &[], &[("PORT", "5000")]).unwrap();
+        assert_eq!(config.port, 5000);
+    }
+
+    #[test]
+    fn flag_overrides_env_and_file() {
+        let config = load("port = 4000\n", &["--port", "6000"], &[("PORT", "5000")]).unwrap();
+        assert_eq!(config.port, 6000);
+
+        let config = load("port = 4000\n", &["--port=6000"], &[]).unwrap();
+        assert_eq!(config.port, 6000);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = load("prot = 4000\n", &[], &[]).unwrap_err();
+        assert!(err.to_string().contains("unknown option `prot`"), "{err}");
+
+        let err = load("config = \"other.toml\"\n", &[], &[]).unwrap_err();
+        assert!(err.to_string().contains("unknown option `config`"), "{err}");
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let err = load("port = \"http\"\n", &[], &[]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation, "{err}");
+
+        let err = load("cors_allowed_origins = [{ origin = \"*\" }]\n", &[], &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("must be a scalar or a list"),
+            "{err}"
+        );
+    }
+}
@@ -1,3 +1,8 @@
 pub mod application;
+pub mod cli;
+pub mod config;
+pub mod shutdown;
+pub mod tls;
 
 pub use application::Application;
+pub use config::Config;
@@ -0,0 +1,386 @@
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    extract::{connect_info::Connected, Request},
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    serve::{IncomingStream, Listener},
+};
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+use crate::api::middleware::rate_limit::ClientAddr;
+
+use super::shutdown::Shutdown;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("cannot read {0}: {1}")]
+    Read(PathBuf, rustls::pki_types::pem::Error),
+    #[error("{0} contains no certificates")]
+    NoCertificates(PathBuf),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Certificate and key served to clients, replaced on reload without restarting.
+#[derive(Debug)]
+struct Resolver {
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.key.read().ok().map(|key| key.clone())
+    }
+}
+
+/// TLS settings loaded from PEM certificate chain and private key files.
+pub struct Tls {
+    cert: PathBuf,
+    key: PathBuf,
+    provider: Arc<CryptoProvider>,
+    resolver: Arc<Resolver>,
+    acceptor: TlsAcceptor,
+}
+
+impl Tls {
+    pub fn load(cert: &Path, key: &Path) -> Result<Self, Error> {
+        let provider = Arc::new(ring::default_provider());
+
+        let resolver = Arc::new(Resolver {
+            key: RwLock::new(Arc::new(certified_key(cert, key, &provider)?)),
+        });
+
+        let mut config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Self {
+            cert: cert.to_owned(),
+            key: key.to_owned(),
+            provider,
+            resolver,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        })
+    }
+
+    /// Reads the files again, new connections get the new certificate.
+    pub fn reload(&self) -> Result<(), Error> {
+        let key = certified_key(&self.cert, &self.key, &self.provider)?;
+
+        if let Ok(mut current) = self.resolver.key.write() {
+            *current = Arc::new(key);
+        }
+
+        Ok(())
+    }
+
+    /// Reloads the certificate on SIGHUP or when the files change, until shutdown.
+    pub async fn watch(self: Arc<Self>, shutdown: Shutdown) {
+        let mut modified = self.modified();
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        let mut hangup = Hangup::new();
+        let shutdown = shutdown.wait();
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => return,
+                _ = hangup.recv() => tracing::info!("SIGHUP received, reloading the TLS certificate"),
+                _ = interval.tick() => {
+                    let current = self.modified();
+
+                    if current == modified {
+                        continue;
+                    }
+
+                    modified = current;
+                    tracing::info!("TLS certificate files changed, reloading");
+                }
+            }
+
+            match self.reload() {
+                Ok(()) => tracing::info!("TLS certificate reloaded"),
+                Err(err) => tracing::error!("cannot reload the TLS certificate: {err}"),
+            }
+        }
+    }
+
+    pub fn listener(&self, listener: TcpListener) -> io::Result<TlsListener> {
+        TlsListener::new(listener, self.acceptor.clone())
+    }
+
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |path: &Path| path.metadata().and_then(|meta| meta.modified()).ok();
+        (modified(&self.cert), modified(&self.key))
+    }
+}
+
+fn certified_key(
+    cert: &Path,
+    key: &Path,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, Error> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| Error::Read(cert.to_owned(), err))?;
+
+    if certs.is_empty() {
+        return Err(Error::NoCertificates(cert.to_owned()));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|err| Error::Read(key.to_owned(), err))?;
+    let key = provider.key_provider.load_private_key(key)?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+/// Listens for SIGHUP from creation on, so signals arriving between two [`Hangup::recv`]
+/// calls are not lost.
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    #[cfg(unix)]
+    fn new() -> Self {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let signal = signal(SignalKind::hangup())
+            .inspect_err(|err| tracing::error!("cannot listen for SIGHUP: {err}"))
+            .ok();
+
+        Self { signal }
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal
+            && signal.recv().await.is_some()
+        {
+            return;
+        }
+
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Accepts TCP connections and completes TLS handshakes concurrently, so a slow client
+/// does not hold up others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    fn new(listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, connections) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = tokio::select! {
+                    // The server is gone.
+                    _ = tx.closed() => return,
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(err) => {
+                            tracing::error!("cannot accept a connection: {err}");
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    },
+                };
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send((stream, addr)).await;
+                        }
+                        Ok(Err(err)) => tracing::debug!("TLS handshake with {addr} failed: {err}"),
+                        Err(_) => tracing::debug!("TLS handshake with {addr} timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self(Some(stream.remote_addr().ip()))
+    }
+}
+
+/// Serves permanent redirects from plain HTTP to HTTPS on `https_port`.
+pub fn redirect_router(https_port: u16) -> axum::Router {
+    axum::Router::new().fallback(move |req: Request| async move { redirect(req, https_port) })
+}
+
+fn redirect(req: Request, https_port: u16) -> Response {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<axum::http::uri::Authority>().ok());
+
+    let Some(host) = host else {
+        return (StatusCode::BAD_REQUEST, "missing Host header").into_response();
+    };
+
+    let authority = match https_port {
+        443 => host.host().to_owned(),
+        port => format!("{}:{port}", host.host()),
+    };
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    match Uri::builder()
+        .scheme("https")
+        .authority(authority)
+        .path_and_query(path)
+        .build()
+    {
+        Ok(uri) => Redirect::permanent(&uri.to_string()).into_response(),
+        Err(_) => (StatusCode::BAD_REQUEST, "invalid Host header").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+    async fn redirect(host: Option<&str>, uri: &str, https_port: u16) -> Response {
+        let mut req = Request::builder().uri(uri);
+
+        if let Some(host) = host {
+            req = req.header(header::HOST, host);
+        }
+
+        redirect_router(https_port)
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn location(res: &Response) -> &str {
+        res.headers()[header::LOCATION].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn redirects_to_https() {
+        let res = redirect(Some("example.com:8080"), "/v1/projects?limit=5", 8443).await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            location(&res),
+            "https://example.com:8443/v1/projects?limit=5"
+        );
+
+        let res = redirect(Some("example.com"), "/", 443).await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location(&res), "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn rejects_redirect_without_host() {
+        let res = redirect(None, "/", 443).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    fn served(tls: &Tls) -> CertificateDer<'static> {
+        tls.resolver.key.read().unwrap().cert[0].clone()
+    }
+
+    fn fixture(name: &str) -> CertificateDer<'static> {
+        CertificateDer::from_pem_file(format!("{FIXTURES}/{name}")).unwrap()
+    }
+
+    #[test]
+    fn reloads_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        let key = dir.path().join("key.pem");
+
+        fs::copy(format!("{FIXTURES}/cert.pem"), &cert).unwrap();
+        fs::copy(format!("{FIXTURES}/key.pem"), &key).unwrap();
+
+        let tls = Tls::load(&cert, &key).unwrap();
+        assert_eq!(served(&tls), fixture("cert.pem"));
+
+        fs::copy(format!("{FIXTURES}/renewed-cert.pem"), &cert).unwrap();
+        fs::copy(format!("{FIXTURES}/renewed-key.pem"), &key).unwrap();
+
+        tls.reload().unwrap();
+        assert_eq!(served(&tls), fixture("renewed-cert.pem"));
+
+        // A broken file keeps the certificate being served.
+        fs::write(&cert, "").unwrap();
+
+        assert!(matches!(tls.reload(), Err(Error::NoCertificates(_))));
+        assert_eq!(served(&tls), fixture("renewed-cert.pem"));
+    }
+}
@@ -0,0 +1,24 @@
+use sqlx::PgPool;
+
+use super::purge::{every, PURGE_INTERVAL};
+
+pub async fn purge(pool: &PgPool, window_hours: u32) -> Result<u64, sqlx::Error> {
+    let window_hours = i32::try_from(window_hours).unwrap_or(i32::MAX);
+
+    let keys = sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE created_at < current_timestamp - make_interval(hours => $1)",
+        window_hours,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(keys.rows_affected())
+}
+
+/// Periodically removes idempotency keys older than `window_hours`.
+pub async fn run_purge(pool: PgPool, window_hours: u32) {
+    every(PURGE_INTERVAL, "expired idempotency keys", || {
+        purge(&pool, window_hours)
+    })
+    .await
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migration, Migrator},
+    PgConnection, PgExecutor, PgPool,
+};
+use tracing::warn;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Version of the newest migration embedded in the binary.
+pub fn expected_version() -> Option<i64> {
+    MIGRATOR.iter().map(|migration| migration.version).max()
+}
+
+/// Version of the newest successfully applied migration.
+pub async fn applied_version<'e, E: PgExecutor<'e>>(
+    executor: E,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT max(version) FROM _sqlx_migrations WHERE success")
+        .fetch_one(executor)
+        .await
+}
+
+/// Checksums of the applied migrations by version.
+pub async fn applied(conn: &mut PgConnection) -> Result<HashMap<i64, Vec<u8>>, MigrateError> {
+    conn.ensure_migrations_table().await?;
+
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum.into_owned()))
+        .collect())
+}
+
+/// Migrations not applied yet, oldest first.
+pub async fn pending(pool: &PgPool) -> Result<Vec<&'static Migration>, MigrateError> {
+    let applied = applied(&mut *pool.acquire().await?).await?;
+
+    Ok(up_migrations()
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .collect())
+}
+
+/// Applies the pending migrations, or only lists them on a dry run.
+pub async fn up(pool: &PgPool, dry_run: bool) -> Result<Vec<&'static Migration>, MigrateError> {
+    locked(pool, async |conn| {
+        let applied = applied(conn).await?;
+        let pending = up_migrations()
+            .filter(|migration| !applied.contains_key(&migration.version))
+            .collect::<Vec<_>>();
+
+        if !dry_run && !pending.is_empty() {
+            MIGRATOR.run(&mut *conn).await?;
+        }
+
+        Ok(pending)
+    })
+    .await
+}
+
+/// Reverts the migrations newer than `target`, by default the latest one, newest first.
+/// Nothing is changed on a dry run.
+pub async fn down(
+    pool: &PgPool,
+    target: Option<i64>,
+    dry_run: bool,
+) -> Result<Vec<&'static Migration>, MigrateError> {
+    locked(pool, async |conn| {
+        let mut versions = applied(conn).await?.into_keys().collect::<Vec<_>>();
+        versions.sort_unstable_by(|a, b| b.cmp(a));
+
+        let target = match target {
+            Some(target) => target,
+            None => versions.get(1).copied().unwrap_or(0),
+        };
+
+        let mut reverted = Vec::new();
+
+        for version in versions.into_iter().filter(|version| *version > target) {
+            let migration = MIGRATOR
+                .iter()
+                .find(|m| m.version == version && m.migration_type.is_down_migration())
+                .ok_or(MigrateError::VersionMissing(version))?;
+
+            reverted.push(migration);
+        }
+
+        if !dry_run && !reverted.is_empty() {
+            MIGRATOR.undo(&mut *conn, target).await?;
+        }
+
+        Ok(reverted)
+    })
+    .await
+}
+
+fn up_migrations() -> impl Iterator<Item = &'static Migration> {
+    MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+}
+
+/// Runs `f` holding the lock sqlx takes while migrating, so instances starting together
+/// migrate one at a time and report only what they changed themselves. The lock is
+/// reentrant, the migrator takes it again in the same session.
+async fn locked<T>(
+    pool: &PgPool,
+    f: impl AsyncFnOnce(&mut PgConnection) -> Result<T, MigrateError>,
+) -> Result<T, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.lock().await?;
+
+    let result = f(&mut conn).await;
+
+    if let Err(err) = conn.unlock().await {
+        // Closing the session releases the lock too.
+        warn!("cannot release the migration lock: {err}");
+        let _ = conn.close().await;
+    }
+
+    result
+}
@@ -1 +1,6 @@
+pub mod idempotency;
 pub mod jwt;
+pub mod migration;
+pub mod purge;
+pub mod telemetry;
+pub mod trash;
@@ -0,0 +1,24 @@
+use std::{future::Future, time::Duration};
+
+use tracing::{error, info};
+
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Runs `purge` every `interval` and logs how many `rows` it removed.
+pub async fn every<F, Fut>(interval: Duration, rows: &str, mut purge: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<u64, sqlx::Error>>,
+{
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        match purge().await {
+            Ok(0) => {}
+            Ok(count) => info!("Purged {count} {rows}"),
+            Err(err) => error!("Failed to purge {rows}: {err}"),
+        }
+    }
+}
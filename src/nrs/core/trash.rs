@@ -0,0 +1,31 @@
+use sqlx::PgPool;
+
+use super::purge::{every, PURGE_INTERVAL};
+
+pub async fn purge(pool: &PgPool, retention_days: u32) -> Result<u64, sqlx::Error> {
+    let retention_days = i32::try_from(retention_days).unwrap_or(i32::MAX);
+
+    let modules = sqlx::query!(
+        "DELETE FROM modules WHERE deleted_at < current_timestamp - make_interval(days => $1)",
+        retention_days,
+    )
+    .execute(pool)
+    .await?;
+
+    let projects = sqlx::query!(
+        "DELETE FROM projects WHERE deleted_at < current_timestamp - make_interval(days => $1)",
+        retention_days,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(modules.rows_affected() + projects.rows_affected())
+}
+
+/// Periodically removes projects and modules kept in the trash longer than `retention_days`.
+pub async fn run_purge(pool: PgPool, retention_days: u32) {
+    every(PURGE_INTERVAL, "trashed rows", || {
+        purge(&pool, retention_days)
+    })
+    .await
+}
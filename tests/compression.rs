@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use nrs::api::router::{Options, Router};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+fn router(compression: bool) -> axum::Router {
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgres://nrs@127.0.0.1:1/nrs")
+        .unwrap();
+
+    Router::new(
+        pool,
+        Options {
+            jwt_secret: "secret".into(),
+            compression,
+            ..Default::default()
+        },
+    )
+    .into_inner()
+}
+
+/// Returns the `Content-Encoding`, `Vary` and body size of the OpenAPI document.
+async fn get(compression: bool, accept_encoding: Option<&str>) -> (Option<String>, String, usize) {
+    let mut req = Request::builder().uri("/openapi.json");
+
+    if let Some(accept_encoding) = accept_encoding {
+        req = req.header(header::ACCEPT_ENCODING, accept_encoding);
+    }
+
+    let res = router(compression)
+        .oneshot(req.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .map(|value: &header::HeaderValue| value.to_str().unwrap().to_string())
+    };
+    let encoding = header(header::CONTENT_ENCODING);
+    let vary = header(header::VARY).unwrap_or_default();
+    let size = res.into_body().collect().await.unwrap().to_bytes().len();
+
+    (encoding, vary, size)
+}
+
+#[tokio::test]
+async fn compresses_accepted_encodings() {
+    let (_, _, plain) = get(true, None).await;
+
+    for encoding in ["gzip", "br", "zstd"] {
+        let (used, vary, size) = get(true, Some(encoding)).await;
+
+        assert_eq!(used.as_deref(), Some(encoding));
+        assert!(vary.contains("accept-encoding"), "vary is `{vary}`");
+        assert!(size < plain, "{encoding} body has {size} of {plain} bytes");
+    }
+}
+
+#[tokio::test]
+async fn sends_identity_unless_accepted_and_enabled() {
+    let (used, _, _) = get(true, None).await;
+    assert_eq!(used, None);
+
+    let (used, _, _) = get(true, Some("identity")).await;
+    assert_eq!(used, None);
+
+    let (used, vary, _) = get(false, Some("gzip")).await;
+    assert_eq!(used, None);
+    assert!(!vary.contains("accept-encoding"), "vary is `{vary}`");
+}
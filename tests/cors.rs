@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use nrs::api::{
+    middleware::cors::Cors,
+    router::{Options, Router},
+};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+fn router(cors: Cors) -> axum::Router {
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgres://nrs@127.0.0.1:1/nrs")
+        .unwrap();
+
+    Router::new(
+        pool,
+        Options {
+            jwt_secret: "secret".into(),
+            cors: Some(cors),
+            ..Default::default()
+        },
+    )
+    .into_inner()
+}
+
+fn preflight(origin: &str, headers: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::OPTIONS)
+        .uri("/v1/projects")
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// A wildcard does not cover `Authorization` in browsers, so requested headers are echoed.
+#[tokio::test]
+async fn preflight_allows_authorization() {
+    let cors = Cors::new(
+        &["https://ide.example.com".into()],
+        &["*".into()],
+        &["*".into()],
+        true,
+        Some(Duration::from_secs(600)),
+    )
+    .unwrap();
+
+    let res = router(cors)
+        .oneshot(preflight(
+            "https://ide.example.com",
+            "authorization,content-type",
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let headers = res.headers();
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://ide.example.com"
+    );
+    assert_eq!(
+        headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+        "authorization,content-type"
+    );
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "POST");
+    assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+}
+
+#[tokio::test]
+async fn preflight_refuses_unknown_origin() {
+    let cors = Cors::new(
+        &["https://ide.example.com".into()],
+        &["GET".into(), "POST".into()],
+        &["authorization".into()],
+        false,
+        None,
+    )
+    .unwrap();
+
+    let res = router(cors)
+        .oneshot(preflight("https://evil.example.com", "authorization"))
+        .await
+        .unwrap();
+
+    assert!(!res
+        .headers()
+        .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+}
+
+#[test]
+fn rejects_invalid_settings() {
+    let any = ["*".to_string()];
+
+    assert!(Cors::new(&any, &any, &any, true, None).is_err());
+    assert!(Cors::new(&["https://a.example.com/".into()], &any, &any, false, None).is_err());
+    assert!(Cors::new(&["a.example.com".into()], &any, &any, false, None).is_err());
+    assert!(Cors::new(&["http://localhost:8080".into()], &any, &any, true, None).is_ok());
+}
@@ -0,0 +1,136 @@
+use axum::{
+    body::Body,
+    http::{header, Request, Response, StatusCode},
+};
+use http_body_util::BodyExt;
+use nrs::{
+    api::router::{Options, Router},
+    core::jwt,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+const SECRET: &str = "secret";
+
+struct App {
+    router: axum::Router,
+    token: String,
+}
+
+impl App {
+    async fn new(pool: PgPool) -> Self {
+        let user_id = sqlx::query_scalar!(
+            "INSERT INTO users (login, full_name, email, password)
+            VALUES ('user', 'User', 'user@example.com', '') RETURNING id"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let router = Router::new(
+            pool,
+            Options {
+                jwt_secret: SECRET.into(),
+                ..Default::default()
+            },
+        )
+        .into_inner();
+
+        let token = jwt::create_token(user_id, "user@example.com", SECRET).unwrap();
+
+        Self { router, token }
+    }
+
+    async fn create_project(&self, key: &str, name: &str) -> Response<Body> {
+        let body = json!({ "name": name, "target": 0, "description": "" });
+
+        let req = Request::post("/v1/projects")
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("idempotency-key", key)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        self.router.clone().oneshot(req).await.unwrap()
+    }
+}
+
+async fn json(res: Response<Body>) -> Value {
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn projects(pool: &PgPool) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM projects"#)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn replays_response_for_same_key(pool: PgPool) {
+    let app = App::new(pool.clone()).await;
+
+    let first = app.create_project("key", "project").await;
+    assert_eq!(first.status(), StatusCode::OK);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first = json(first).await;
+
+    let retry = app.create_project("key", "project").await;
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    assert_eq!(retry.headers()[header::CONTENT_TYPE], "application/json");
+    assert_eq!(json(retry).await, first);
+
+    assert_eq!(projects(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn rejects_key_reused_with_different_request(pool: PgPool) {
+    let app = App::new(pool.clone()).await;
+
+    let first = app.create_project("key", "project").await;
+    assert_eq!(first.status(), StatusCode::OK);
+
+    let other = app.create_project("key", "other").await;
+    assert_eq!(other.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json(other).await["code"], "unprocessable_entity");
+
+    assert_eq!(projects(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn rejects_key_of_request_in_progress(pool: PgPool) {
+    let app = App::new(pool.clone()).await;
+
+    let first = app.create_project("key", "project").await;
+    assert_eq!(first.status(), StatusCode::OK);
+
+    // Same state as while the first request is still being handled.
+    sqlx::query!("UPDATE idempotency_keys SET status = NULL, content_type = NULL, body = NULL")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let retry = app.create_project("key", "project").await;
+    assert_eq!(retry.status(), StatusCode::CONFLICT);
+    assert_eq!(json(retry).await["code"], "in_progress");
+
+    assert_eq!(projects(&pool).await, 1);
+}
+
+#[sqlx::test]
+async fn requires_authentication(pool: PgPool) {
+    let app = App::new(pool).await;
+
+    let req = Request::post("/v1/projects/1/modules")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header("idempotency-key", "key")
+        .body(Body::from(json!({ "name": "module" }).to_string()))
+        .unwrap();
+
+    let res = app.router.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
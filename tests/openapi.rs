@@ -0,0 +1,247 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use nrs::api::{
+    openapi,
+    router::{Options, Router},
+};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+/// Every operation of the OpenAPI document must be served by the router, a route missing
+/// from the router answers with an empty 404 or a 405 instead of a problem response.
+#[tokio::test]
+async fn spec_matches_routes() {
+    let router = router();
+    let operations = operations();
+
+    assert!(!operations.is_empty());
+
+    for (method, path, _) in operations {
+        let req = Request::builder()
+            .method(method.clone())
+            .uri(uri(&path))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+
+        let res = router.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+
+        assert_ne!(
+            status,
+            StatusCode::METHOD_NOT_ALLOWED,
+            "{method} {path} is documented but not routed"
+        );
+        assert!(
+            status != StatusCode::NOT_FOUND || !body.is_empty(),
+            "{method} {path} is documented but not routed"
+        );
+    }
+}
+
+/// Every route must be documented, and only those. Axum cannot list the routes of a router,
+/// so they are kept in [`ROUTES`], which [`spec_matches_routes`] checks against the router.
+#[test]
+fn routes_are_documented() {
+    let documented = operations()
+        .into_iter()
+        .map(|(method, path, id)| format!("{method} {path} {id}"))
+        .collect::<BTreeSet<_>>();
+
+    let routed = ROUTES
+        .iter()
+        .map(|(method, path, id)| format!("{method} {path} {id}"))
+        .collect::<BTreeSet<_>>();
+
+    let undocumented = routed.difference(&documented).collect::<Vec<_>>();
+    let unlisted = documented.difference(&routed).collect::<Vec<_>>();
+
+    assert!(
+        undocumented.is_empty(),
+        "routed but not documented: {undocumented:?}"
+    );
+    assert!(
+        unlisted.is_empty(),
+        "documented but missing from ROUTES: {unlisted:?}"
+    );
+}
+
+/// Methods missing from [`ROUTES`] must not be served on its paths, so a method added to an
+/// existing route cannot go undocumented.
+#[tokio::test]
+async fn unlisted_methods_are_not_routed() {
+    let router = router();
+
+    for (_, path, _) in ROUTES {
+        for method in METHODS {
+            if ROUTES
+                .iter()
+                .any(|route| route.0 == method.as_str() && route.1 == path)
+            {
+                continue;
+            }
+
+            let req = Request::builder()
+                .method(method.clone())
+                .uri(uri(path))
+                .body(Body::empty())
+                .unwrap();
+
+            let res = router.clone().oneshot(req).await.unwrap();
+
+            assert_eq!(
+                res.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {path} is routed but missing from ROUTES"
+            );
+        }
+    }
+}
+
+#[test]
+fn operation_ids_are_unique() {
+    let mut ids = operations()
+        .into_iter()
+        .map(|(_, _, id)| id)
+        .collect::<Vec<_>>();
+
+    let count = ids.len();
+    ids.sort();
+    ids.dedup();
+
+    assert_eq!(ids.len(), count);
+}
+
+const METHODS: [Method; 5] = [
+    Method::GET,
+    Method::PUT,
+    Method::POST,
+    Method::DELETE,
+    Method::PATCH,
+];
+
+/// Method, path and operation id of every route of the router.
+const ROUTES: [(&str, &str, &str); 27] = [
+    ("GET", "/openapi.json", "get"),
+    ("GET", "/v1/account", "account_get_one"),
+    ("PUT", "/v1/account", "account_update"),
+    ("POST", "/v1/account", "account_create"),
+    ("DELETE", "/v1/account", "account_delete"),
+    ("POST", "/v1/account/login", "account_login"),
+    ("PUT", "/v1/account/password", "account_change_password"),
+    ("GET", "/v1/projects", "project_get_all"),
+    ("POST", "/v1/projects", "project_create"),
+    ("GET", "/v1/projects/trash", "project_get_trash"),
+    ("GET", "/v1/projects/{id}", "project_get_one"),
+    ("PUT", "/v1/projects/{id}", "project_update"),
+    ("DELETE", "/v1/projects/{id}", "project_delete"),
+    ("POST", "/v1/projects/{id}/restore", "project_restore"),
+    ("GET", "/v1/projects/{project_id}/modules", "module_get_all"),
+    ("POST", "/v1/projects/{project_id}/modules", "module_create"),
+    (
+        "POST",
+        "/v1/projects/{project_id}/modules/batch",
+        "module_batch",
+    ),
+    (
+        "GET",
+        "/v1/projects/{project_id}/modules/trash",
+        "module_get_trash",
+    ),
+    (
+        "GET",
+        "/v1/projects/{project_id}/modules/{id}",
+        "module_get_one",
+    ),
+    (
+        "PUT",
+        "/v1/projects/{project_id}/modules/{id}",
+        "module_update",
+    ),
+    (
+        "DELETE",
+        "/v1/projects/{project_id}/modules/{id}",
+        "module_delete",
+    ),
+    (
+        "POST",
+        "/v1/projects/{project_id}/modules/{id}/copy",
+        "module_copy",
+    ),
+    (
+        "GET",
+        "/v1/projects/{project_id}/modules/{id}/dependencies",
+        "module_get_dependencies",
+    ),
+    (
+        "PUT",
+        "/v1/projects/{project_id}/modules/{id}/dependencies",
+        "module_set_dependencies",
+    ),
+    (
+        "GET",
+        "/v1/projects/{project_id}/modules/{id}/dependents",
+        "module_get_dependents",
+    ),
+    (
+        "POST",
+        "/v1/projects/{project_id}/modules/{id}/restore",
+        "module_restore",
+    ),
+    ("GET", "/v1/search", "search_search"),
+];
+
+fn router() -> axum::Router {
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgres://nrs@127.0.0.1:1/nrs")
+        .unwrap();
+
+    Router::new(
+        pool,
+        Options {
+            jwt_secret: "secret".to_string(),
+            ..Default::default()
+        },
+    )
+    .into_inner()
+}
+
+/// Fills the parameters of a documented path.
+fn uri(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.starts_with('{') {
+                "1"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Method, path and operation id of every operation of the OpenAPI document.
+fn operations() -> Vec<(Method, String, String)> {
+    let spec = openapi::spec();
+    let mut operations = Vec::new();
+
+    for (path, item) in spec.paths.paths {
+        let methods = [item.get, item.put, item.post, item.delete, item.patch];
+
+        for (method, operation) in METHODS.into_iter().zip(methods) {
+            if let Some(operation) = operation {
+                let id = operation.operation_id.unwrap_or_default();
+                operations.push((method, path.clone(), id));
+            }
+        }
+    }
+
+    operations
+}
@@ -0,0 +1,74 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use nrs::api::{
+    middleware::rate_limit::{Budgets, RateLimiter},
+    router::{Options, Router},
+};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn rejects_requests_over_budget() {
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgres://nrs@127.0.0.1:1/nrs")
+        .unwrap();
+
+    let router = Router::new(
+        pool,
+        Options {
+            jwt_secret: "secret".into(),
+            rate_limit: Some(Arc::new(RateLimiter::new(
+                Budgets {
+                    read: 2,
+                    write: 0,
+                    auth: 0,
+                },
+                false,
+            ))),
+            ..Default::default()
+        },
+    )
+    .into_inner();
+
+    let get = || {
+        Request::builder()
+            .uri("/v1/projects")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // Missing tokens are rejected by the handler, after counting against the budget.
+    for remaining in ["1", "0"] {
+        let res = router.clone().oneshot(get()).await.unwrap();
+
+        assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["ratelimit-limit"], "2");
+        assert_eq!(res.headers()["ratelimit-remaining"], remaining);
+    }
+
+    let res = router.clone().oneshot(get()).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers()["ratelimit-policy"], "2;w=60");
+    assert_eq!(res.headers()[header::RETRY_AFTER], "30");
+
+    // Other route classes have their own budget.
+    let res = router
+        .oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri("/v1/projects/1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(!res.headers().contains_key("ratelimit-limit"));
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use nrs::{
+    api::router::{Options, Router},
+    core::jwt,
+};
+use serde_json::Value;
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+
+/// Sends an authenticated request and returns the status and problem document of the response.
+async fn send(method: Method, uri: &str, body: &'static str) -> (StatusCode, Value) {
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgres://nrs@127.0.0.1:1/nrs")
+        .unwrap();
+
+    let router = Router::new(
+        pool,
+        Options {
+            jwt_secret: "secret".into(),
+            ..Default::default()
+        },
+    )
+    .into_inner();
+
+    let token = jwt::create_token(1, "user@example.com", "secret").unwrap();
+
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+
+    let res = router.oneshot(req).await.unwrap();
+
+    assert_eq!(
+        res.headers()[header::CONTENT_TYPE],
+        "application/problem+json",
+        "{uri}"
+    );
+
+    let status = res.status();
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn rejects_invalid_path_params_with_problem() {
+    for (method, uri) in [
+        (Method::GET, "/v1/projects/abc"),
+        (Method::DELETE, "/v1/projects/1/modules/abc"),
+        (Method::PUT, "/v1/projects/abc/modules/1"),
+    ] {
+        let (status, problem) = send(method, uri, "").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        assert_eq!(problem["code"], "bad_request", "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn rejects_invalid_query_params_with_problem() {
+    for (method, uri) in [
+        (Method::DELETE, "/v1/projects/1/modules/1?force=maybe"),
+        (Method::GET, "/v1/search?q=module&limit=x"),
+        (Method::GET, "/v1/projects?limit=x"),
+    ] {
+        let (status, problem) = send(method, uri, "").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        assert_eq!(problem["code"], "bad_request", "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn rejects_invalid_json_with_problem() {
+    let (status, problem) = send(Method::POST, "/v1/account/login", r#"{"email":"#).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(problem["code"], "invalid_json");
+}
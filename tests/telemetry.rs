@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, HeaderValue},
+    routing::post,
+};
+use nrs::core::telemetry;
+use opentelemetry::trace::TracerProvider as _;
+use tracing_subscriber::{filter::Targets, prelude::*, Registry};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+/// Spans continuing an incoming `traceparent`, including query spans, are exported to
+/// the collector stand-in.
+#[tokio::test(flavor = "multi_thread")]
+async fn exports_spans_to_collector() {
+    let received = Arc::new(Mutex::new(Vec::<Bytes>::new()));
+
+    let collector = axum::Router::new()
+        .route(
+            "/v1/traces",
+            post(
+                |State(received): State<Arc<Mutex<Vec<Bytes>>>>, body: Bytes| async move {
+                    received.lock().unwrap().push(body);
+                },
+            ),
+        )
+        .with_state(received.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, collector).await });
+
+    let provider = telemetry::init(&endpoint, 0.0, "nrs-test").unwrap();
+    let tracer = provider.tracer("nrs");
+
+    let subscriber = Registry::default()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer.clone()))
+        .with(
+            telemetry::QuerySpans::new(tracer)
+                .with_filter(Targets::new().with_target("sqlx::query", tracing::Level::DEBUG)),
+        );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "traceparent",
+        HeaderValue::from_str(&format!("00-{TRACE_ID}-00f067aa0ba902b7-01")).unwrap(),
+    );
+
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("request");
+        telemetry::set_parent(&span, &headers);
+
+        span.in_scope(|| {
+            tracing::debug!(
+                target: "sqlx::query",
+                summary = "SELECT 1",
+                db.statement = "",
+                rows_affected = 0_u64,
+                rows_returned = 1_u64,
+                elapsed_secs = 0.001,
+            );
+        });
+    });
+
+    tokio::task::spawn_blocking(move || provider.shutdown().unwrap())
+        .await
+        .unwrap();
+
+    let received = received.lock().unwrap().concat();
+    let contains = |needle: &[u8]| {
+        received
+            .windows(needle.len())
+            .any(|window| window == needle)
+    };
+
+    // The parent was sampled, so the zero ratio does not apply.
+    assert!(contains(&hex(TRACE_ID)));
+    assert!(contains(b"request"));
+    assert!(contains(b"SELECT 1"));
+    assert!(contains(b"nrs-test"));
+}
+
+fn hex(value: &str) -> Vec<u8> {
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+        .collect()
+}